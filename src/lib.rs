@@ -1,8 +1,12 @@
-use failure::Error;
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use failure::{Error, Fail, ResultExt};
 use reqwest::{self as rq, Proxy, RequestBuilder};
 use sha3::{Digest, Sha3_256};
 use url::Host;
 
+mod wire;
+
 pub struct Pubkey(pub [u8; 32]);
 impl AsRef<[u8; 32]> for Pubkey {
     fn as_ref(&self) -> &[u8; 32] {
@@ -10,8 +14,67 @@ impl AsRef<[u8; 32]> for Pubkey {
     }
 }
 
+/// Broad category of a failure, used by the CLI to pick an exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Auth,
+    Network,
+    InvalidAddress,
+    Decode,
+    Usage,
+}
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Auth => "authentication failed",
+            ErrorKind::Network => "network error",
+            ErrorKind::InvalidAddress => "invalid address",
+            ErrorKind::Decode => "invalid response",
+            ErrorKind::Usage => "invalid usage",
+        })
+    }
+}
+impl Fail for ErrorKind {}
+impl ErrorKind {
+    pub fn of(err: &Error) -> Option<ErrorKind> {
+        err.iter_chain()
+            .filter_map(|f| f.downcast_ref::<failure::Context<ErrorKind>>())
+            .map(|c| *c.get_context())
+            .next()
+    }
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Usage => 2,
+            ErrorKind::Auth => 3,
+            ErrorKind::Network => 4,
+            ErrorKind::InvalidAddress => 5,
+            ErrorKind::Decode => 6,
+        }
+    }
+}
+
+fn check_status(status: rq::StatusCode) -> Result<(), Error> {
+    if status.is_success() {
+        return Ok(());
+    }
+    let kind = if status == rq::StatusCode::UNAUTHORIZED {
+        ErrorKind::Auth
+    } else {
+        ErrorKind::Network
+    };
+    Err(
+        failure::format_err!("{}", status.canonical_reason().unwrap_or("UNKNOWN STATUS"))
+            .context(kind)
+            .into(),
+    )
+}
+
 pub fn onion_to_pubkey(onion: &str) -> Result<Pubkey, Error> {
-    let s = onion.split(".").next().unwrap();
+    Ok(decode_onion(onion).context(ErrorKind::InvalidAddress)?)
+}
+
+fn decode_onion(onion: &str) -> Result<Pubkey, Error> {
+    let s = onion.split('.').next().unwrap();
     let b = base32::decode(base32::Alphabet::RFC4648 { padding: false }, s)
         .ok_or_else(|| failure::format_err!("invalid base32"))?;
     failure::ensure!(b.len() >= 35, "invalid base32 length");
@@ -20,8 +83,8 @@ pub fn onion_to_pubkey(onion: &str) -> Result<Pubkey, Error> {
     let mut hasher = Sha3_256::new();
     hasher.input(b".onion checksum");
     hasher.input(pubkey);
-    hasher.input(&[3]);
-    failure::ensure!(&b[32..34] == &hasher.result()[..2], "invalid checksum");
+    hasher.input([3]);
+    failure::ensure!(b[32..34] == hasher.result()[..2], "invalid checksum");
     let mut pk = [0; 32];
     pk.clone_from_slice(pubkey);
     Ok(Pubkey(pk))
@@ -34,7 +97,7 @@ pub fn pubkey_to_onion(pubkey: &[u8]) -> Result<String, Error> {
     let mut hasher = Sha3_256::new();
     hasher.input(b".onion checksum");
     hasher.input(pubkey);
-    hasher.input(&[3]);
+    hasher.input([3]);
     let mut onion = Vec::with_capacity(35);
     onion.extend_from_slice(pubkey);
     onion.extend_from_slice(&hasher.result()[..2]);
@@ -48,8 +111,8 @@ pub fn pubkey_to_onion(pubkey: &[u8]) -> Result<String, Error> {
 #[derive(Clone, Debug)]
 pub struct Creds {
     pub host: Host,
-    pub proxy: Option<Proxy>,
     pub password: String,
+    client: rq::Client,
 }
 impl AsRef<Creds> for Creds {
     fn as_ref(&self) -> &Creds {
@@ -57,65 +120,275 @@ impl AsRef<Creds> for Creds {
     }
 }
 impl Creds {
-    pub fn get(&self, rel_url: &str) -> Result<RequestBuilder, Error> {
-        Ok(if let Some(proxy) = &self.proxy {
-            rq::Client::builder().proxy(proxy.clone()).build()?
+    /// Builds the one client every request is sent with.
+    pub fn new(host: Host, proxy: Option<Proxy>, password: String) -> Result<Self, Error> {
+        let client = if let Some(proxy) = proxy {
+            rq::Client::builder()
+                .proxy(proxy)
+                .build()
+                .context(ErrorKind::Network)?
         } else {
             rq::Client::new()
+        };
+        Ok(Creds {
+            host,
+            password,
+            client,
+        })
+    }
+    pub fn get(&self, rel_url: &str) -> RequestBuilder {
+        self.client
+            .get(&format!("http://{}:59001/{}", self.host, rel_url))
+            .basic_auth("me", Some(&self.password))
+    }
+    pub fn post<T: Into<rq::Body>>(&self, body: T) -> RequestBuilder {
+        self.client
+            .post(&format!("http://{}:59001", self.host))
+            .basic_auth("me", Some(&self.password))
+            .body(body)
+    }
+    /// Sends a request from `get` or `post`, tracing it with the credentials redacted.
+    pub async fn send(&self, req: RequestBuilder) -> Result<rq::Response, Error> {
+        let req = req.build().context(ErrorKind::Network)?;
+        tracing::info!(method = %req.method(), url = %req.url(), "sending request");
+        for (name, value) in req.headers() {
+            if name == rq::header::AUTHORIZATION {
+                tracing::debug!(header = %name, value = "<redacted>");
+            } else {
+                tracing::debug!(header = %name, value = ?value);
+            }
+        }
+        if let Some(len) = req.body().and_then(|b| b.as_bytes()).map(|b| b.len()) {
+            tracing::debug!(bytes = len, "request body");
         }
-        .get(&format!("http://{}:59001/{}", self.host, rel_url))
-        .basic_auth("me", Some(&self.password)))
+        let start = std::time::Instant::now();
+        let res = self.client.execute(req).await.context(ErrorKind::Network)?;
+        tracing::info!(
+            status = %res.status(),
+            elapsed_ms = start.elapsed().as_millis() as u64,
+            "received response"
+        );
+        check_status(res.status())?;
+        Ok(res)
     }
-    pub fn post<T: Into<rq::Body>>(&self, body: T) -> Result<RequestBuilder, Error> {
-        Ok(if let Some(proxy) = &self.proxy {
-            rq::Client::builder().proxy(proxy.clone()).build()?
-        } else {
-            rq::Client::new()
+}
+
+/// Largest response body the client will buffer.
+pub const MAX_RESPONSE_SIZE: usize = 32 * 1024 * 1024;
+/// Largest single message the client will decode.
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+/// Largest contact name the client will decode.
+pub const MAX_NAME_SIZE: usize = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    ResponseTooLarge {
+        limit: usize,
+    },
+    FieldTooLarge {
+        field: &'static str,
+        offset: usize,
+        len: u64,
+        limit: usize,
+    },
+    UnexpectedEof {
+        field: &'static str,
+        offset: usize,
+        needed: usize,
+        remaining: usize,
+    },
+    InvalidUtf8 {
+        field: &'static str,
+        offset: usize,
+    },
+}
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DecodeError::ResponseTooLarge { limit } => {
+                write!(f, "response exceeds the {} byte limit", limit)
+            }
+            DecodeError::FieldTooLarge {
+                field,
+                offset,
+                len,
+                limit,
+            } => write!(
+                f,
+                "{} at offset {:#06x} is {} bytes, exceeding the {} byte limit",
+                field, offset, len, limit
+            ),
+            DecodeError::UnexpectedEof {
+                field,
+                offset,
+                needed,
+                remaining,
+            } => write!(
+                f,
+                "unexpected end of response: {} needs {} bytes at offset {:#06x}, {} remaining",
+                field, needed, offset, remaining
+            ),
+            DecodeError::InvalidUtf8 { field, offset } => {
+                write!(f, "{} at offset {:#06x} is not valid utf-8", field, offset)
+            }
+        }
+    }
+}
+impl Fail for DecodeError {}
+
+async fn read_body(mut res: rq::Response) -> Result<Vec<u8>, Error> {
+    let too_large = || {
+        Error::from(DecodeError::ResponseTooLarge {
+            limit: MAX_RESPONSE_SIZE,
+        })
+        .context(ErrorKind::Decode)
+    };
+    let start = std::time::Instant::now();
+    let mut body = Vec::new();
+    if let Some(len) = res.content_length() {
+        if len > MAX_RESPONSE_SIZE as u64 {
+            return Err(too_large().into());
+        }
+        body.reserve(len as usize);
+    }
+    while let Some(chunk) = res.chunk().await.context(ErrorKind::Network)? {
+        if body.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            return Err(too_large().into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    tracing::debug!(
+        bytes = body.len(),
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "read response body"
+    );
+    Ok(body)
+}
+
+/// A problem with a single record that did not prevent decoding the rest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeWarning {
+    pub record: usize,
+    pub error: DecodeError,
+}
+impl std::fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "record {}: {}", self.record, self.error)
+    }
+}
+
+/// Records decoded from a response, along with any records that were damaged.
+#[derive(Clone, Debug)]
+pub struct Decoded<T> {
+    pub records: Vec<T>,
+    pub warnings: Vec<DecodeWarning>,
+    /// records the response held, counting those that were skipped
+    pub received: usize,
+}
+
+/// Text from the wire. Invalid utf-8 keeps its original bytes alongside a
+/// lossy version for display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Text {
+    Utf8(String),
+    Invalid { raw: Vec<u8>, lossy: String },
+}
+impl Text {
+    pub fn from_bytes(raw: Vec<u8>) -> Self {
+        match String::from_utf8(raw) {
+            Ok(s) => Text::Utf8(s),
+            Err(e) => {
+                let raw = e.into_bytes();
+                Text::Invalid {
+                    lossy: String::from_utf8_lossy(&raw).into_owned(),
+                    raw,
+                }
+            }
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Text::Utf8(s) => s,
+            Text::Invalid { lossy, .. } => lossy,
+        }
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Text::Utf8(s) => s.as_bytes(),
+            Text::Invalid { raw, .. } => raw,
+        }
+    }
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Text::Utf8(_))
+    }
+}
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl From<String> for Text {
+    fn from(s: String) -> Self {
+        Text::Utf8(s)
+    }
+}
+
+/// Decodes records one at a time. `record` returns `None` for a record it had
+/// to skip, and an error only when the framing is lost and nothing after it
+/// can be trusted.
+fn decode<T, F: FnMut(&mut wire::Reader) -> Result<Option<T>, DecodeError>>(
+    body: &[u8],
+    mut record: F,
+) -> Decoded<T> {
+    let mut r = wire::Reader::new(body);
+    let mut records = Vec::new();
+    while !r.is_empty() {
+        r.begin_record();
+        match record(&mut r) {
+            Ok(Some(rec)) => records.push(rec),
+            Ok(None) => (),
+            Err(e) => {
+                r.warn(e);
+                break;
+            }
         }
-        .post(&format!("http://{}:59001", self.host))
-        .basic_auth("me", Some(&self.password))
-        .body(body))
+    }
+    tracing::trace!("response body: {}", r.dump());
+    let received = r.records();
+    let warnings = r.into_warnings();
+    for warning in &warnings {
+        tracing::warn!("damaged record in response: {}", warning);
+    }
+    Decoded {
+        records,
+        warnings,
+        received,
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct UserData {
     pub id: [u8; 32],
-    pub name: Option<String>,
+    pub name: Option<Text>,
     pub unreads: u64,
 }
 
-pub async fn fetch_users<C: AsRef<Creds>>(creds: C) -> Result<Vec<UserData>, Error> {
-    use std::io::Read;
-    let mut users = Vec::new();
+pub async fn fetch_users<C: AsRef<Creds>>(creds: C) -> Result<Decoded<UserData>, Error> {
+    let creds = creds.as_ref();
+    let res = creds.send(creds.get("?type=users")).await?;
+    Ok(decode(&read_body(res).await?, decode_user))
+}
 
-    let res = creds.as_ref().get("?type=users")?.send().await?;
-    let status = res.status();
-    if !status.is_success() {
-        failure::bail!("{}", status.canonical_reason().unwrap_or("UNKNOWN STATUS"));
-    }
-    let mut b = std::io::Cursor::new(res.bytes().await?);
-    loop {
-        let mut id = [0; 32];
-        match b.read_exact(&mut id) {
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-            a => a?,
-        };
-        let mut buf = [0; 8];
-        b.read_exact(&mut buf)?;
-        let unreads = u64::from_be_bytes(buf);
-        let mut buf = [0];
-        b.read_exact(&mut buf)?;
-        let name = if buf[0] == 0 {
-            None
-        } else {
-            let mut buf = vec![0; buf[0] as usize];
-            b.read_exact(&mut buf)?;
-            Some(String::from_utf8(buf)?)
-        };
-        users.push(UserData { id, name, unreads })
-    }
-    Ok(users)
+fn decode_user(r: &mut wire::Reader) -> Result<Option<UserData>, DecodeError> {
+    let id = r.array32("id")?;
+    let unreads = r.u64("unreads")?;
+    let len = r.u8("name_len")?;
+    let name = if len == 0 {
+        None
+    } else {
+        r.text("name", len as u64, MAX_NAME_SIZE)?
+    };
+    Ok(Some(UserData { id, name, unreads }))
 }
 
 pub async fn add_user(creds: &Creds, onion: &str, name: &str) -> Result<(), Error> {
@@ -123,10 +396,7 @@ pub async fn add_user(creds: &Creds, onion: &str, name: &str) -> Result<(), Erro
     req.push(1);
     req.extend_from_slice(onion_to_pubkey(onion)?.as_ref());
     req.extend_from_slice(name.as_bytes());
-    let status = creds.post(req)?.send().await?.status();
-    if !status.is_success() {
-        failure::bail!("{}", status.canonical_reason().unwrap_or("UNKNOWN STATUS"));
-    }
+    creds.send(creds.post(req)).await?;
     Ok(())
 }
 
@@ -134,65 +404,132 @@ pub async fn add_user(creds: &Creds, onion: &str, name: &str) -> Result<(), Erro
 pub struct Message {
     pub inbound: bool,
     pub time: i64,
-    pub content: String,
+    pub content: Text,
+}
+impl Message {
+    pub fn datetime(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(self.time, 0).single()
+    }
+    pub fn format_time(&self, fmt: &TimeFormat) -> String {
+        self.format_time_at(fmt, Utc::now())
+    }
+    /// The timestamp as it reads at `now`, which only matters if `fmt` is
+    /// relative.
+    pub fn format_time_at(&self, fmt: &TimeFormat, now: DateTime<Utc>) -> String {
+        match self.datetime() {
+            Some(time) => fmt.format_at(time, now),
+            None => format!("{}", self.time),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+#[derive(Clone, Debug)]
+pub struct TimeFormat {
+    pub zone: Zone,
+    /// strftime style format string
+    pub format: String,
+    /// render as "5m ago" instead of an absolute time
+    pub relative: bool,
+}
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat {
+            zone: Zone::Local,
+            format: "%Y-%m-%d %H:%M:%S %:z".to_owned(),
+            relative: false,
+        }
+    }
+}
+impl TimeFormat {
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        self.format_at(time, Utc::now())
+    }
+    pub fn format_at(&self, time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        if self.relative {
+            self.format_relative(time, now)
+        } else {
+            self.format_absolute(time)
+        }
+    }
+    pub fn format_absolute(&self, time: DateTime<Utc>) -> String {
+        match self.zone {
+            Zone::Local => time.with_timezone(&Local).format(&self.format).to_string(),
+            Zone::Utc => time.format(&self.format).to_string(),
+            Zone::Named(tz) => time.with_timezone(&tz).format(&self.format).to_string(),
+        }
+    }
+    /// The calendar day `time` falls on in the configured zone.
+    pub fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        match self.zone {
+            Zone::Local => time.with_timezone(&Local).naive_local().date(),
+            Zone::Utc => time.naive_utc().date(),
+            Zone::Named(tz) => time.with_timezone(&tz).naive_local().date(),
+        }
+    }
+    pub fn format_relative(&self, time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let diff = now.signed_duration_since(time);
+        let secs = diff.num_seconds().abs();
+        let amount = if secs < 60 {
+            return "just now".to_owned();
+        } else if secs < 60 * 60 {
+            format!("{}m", secs / 60)
+        } else if secs < 60 * 60 * 24 {
+            format!("{}h", secs / (60 * 60))
+        } else if secs < 60 * 60 * 24 * 365 {
+            format!("{}d", secs / (60 * 60 * 24))
+        } else {
+            format!("{}y", secs / (60 * 60 * 24 * 365))
+        };
+        if diff.num_seconds() < 0 {
+            format!("in {}", amount)
+        } else {
+            format!("{} ago", amount)
+        }
+    }
 }
 
 pub async fn fetch_messages<C: AsRef<Creds>, I: AsRef<[u8; 32]>>(
     creds: C,
     id: I,
     limit: Option<usize>,
-) -> Result<Vec<Message>, Error> {
-    use std::io::Read;
-
-    let mut msgs = Vec::new();
-    let res = creds
-        .as_ref()
-        .get(&if let Some(limit) = limit {
-            format!(
-                "?type=messages&pubkey={}&limit={}",
-                base32::encode(base32::Alphabet::RFC4648 { padding: false }, id.as_ref())
-                    .to_lowercase(),
-                limit
-            )
-        } else {
-            format!(
-                "?type=messages&pubkey={}",
-                base32::encode(base32::Alphabet::RFC4648 { padding: false }, id.as_ref())
-                    .to_lowercase()
-            )
-        })?
-        .send()
-        .await?;
-    let status = res.status();
-    if !status.is_success() {
-        failure::bail!("{}", status.canonical_reason().unwrap_or("UNKNOWN STATUS"));
-    }
-    let mut b = std::io::Cursor::new(res.bytes().await?);
-
-    loop {
-        let mut buf = [0];
-        match b.read_exact(&mut buf) {
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-            a => a?,
-        };
-        let inbound = buf[0] != 0;
-        b.read_exact(&mut [0; 24])?;
-        let mut buf = [0; 8];
-        b.read_exact(&mut buf)?;
-        let time = i64::from_be_bytes(buf);
-        let mut buf = [0; 8];
-        b.read_exact(&mut buf)?;
-        let len = u64::from_be_bytes(buf);
-        let mut buf = vec![0; len as usize];
-        b.read_exact(&mut buf)?;
-        msgs.push(Message {
+) -> Result<Decoded<Message>, Error> {
+    let creds = creds.as_ref();
+    let req = creds.get(&if let Some(limit) = limit {
+        format!(
+            "?type=messages&pubkey={}&limit={}",
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, id.as_ref())
+                .to_lowercase(),
+            limit
+        )
+    } else {
+        format!(
+            "?type=messages&pubkey={}",
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, id.as_ref())
+                .to_lowercase()
+        )
+    });
+    let res = creds.send(req).await?;
+    Ok(decode(&read_body(res).await?, decode_message))
+}
+
+fn decode_message(r: &mut wire::Reader) -> Result<Option<Message>, DecodeError> {
+    let inbound = r.u8("inbound")? != 0;
+    r.take("reserved", 24)?;
+    let time = r.i64("time")?;
+    let len = r.u64("len")?;
+    Ok(r.text("content", len, MAX_MESSAGE_SIZE)?
+        .map(|content| Message {
             inbound,
             time,
-            content: String::from_utf8(buf)?,
-        });
-    }
-
-    Ok(msgs)
+            content,
+        }))
 }
 
 pub async fn send_message(creds: &Creds, id: &[u8; 32], content: &str) -> Result<(), Error> {
@@ -201,12 +538,9 @@ pub async fn send_message(creds: &Creds, id: &[u8; 32], content: &str) -> Result
     req.extend_from_slice(&[0; 16]);
     req.extend_from_slice(id);
     req.extend_from_slice(content.as_bytes());
-    let status = creds.post(req)?.send().await?.status();
-    if !status.is_success() {
-        failure::bail!(
-            "{}",
-            status.canonical_reason().unwrap_or("UNKNOWN STATUS CODE")
-        );
-    }
+    creds.send(creds.post(req)).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests;
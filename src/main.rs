@@ -1,6 +1,11 @@
-use clap::{App, Arg, SubCommand};
-use failure::Error;
+use chrono::format::{Item, StrftimeItems};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use cupslib::{ErrorKind, Text, TimeFormat, Zone};
+use failure::{Error, ResultExt};
 use reqwest::Proxy;
+use tracing::level_filters::LevelFilter;
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
 use url::Host;
 
 #[cfg(feature = "tui")]
@@ -10,21 +15,93 @@ async fn inner_main() -> Result<(), Error> {
     let host: Option<Host> = std::env::var("CUPS_HOST")
         .ok()
         .map(|a| Host::parse(&a))
-        .transpose()?;
+        .transpose()
+        .context(ErrorKind::InvalidAddress)?;
     let proxy: Option<Proxy> = std::env::var("CUPS_PROXY")
         .ok()
         .map(|a| Proxy::http(&format!("socks5h://{}:9050", a)))
-        .transpose()?;
+        .transpose()
+        .context(ErrorKind::Usage)?;
 
     let app = App::new("Cups CLI")
         .version("0.1.0")
         .author("Aiden McClelland <me@drbonez.dev>")
         .about("Interact with Cups")
+        .after_help(
+            "EXIT CODES:\n    \
+             0    Success\n    \
+             1    Other error\n    \
+             2    Invalid usage\n    \
+             3    Authentication failed\n    \
+             4    Network error\n    \
+             5    Invalid address\n    \
+             6    Invalid response from server",
+        )
         .arg(
             Arg::with_name("password")
                 .long("password")
                 .short("p")
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Log requests (-v), headers (-vv), response hex dumps (-vvv) and HTTP internals (-vvvv)"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .global(true)
+                .help("Write logs to a file instead of stderr"),
+        )
+        .arg(
+            Arg::with_name("no-input")
+                .long("no-input")
+                .global(true)
+                .help("Fail instead of prompting for a password"),
+        )
+        .arg(
+            Arg::with_name("time-format")
+                .long("time-format")
+                .takes_value(true)
+                .global(true)
+                .help("strftime style format for message timestamps"),
+        )
+        .arg(
+            Arg::with_name("utc")
+                .long("utc")
+                .global(true)
+                .conflicts_with("tz")
+                .help("Display timestamps in UTC"),
+        )
+        .arg(
+            Arg::with_name("tz")
+                .long("tz")
+                .takes_value(true)
+                .global(true)
+                .help("Display timestamps in the given time zone (e.g. America/Denver)"),
+        )
+        .arg(
+            Arg::with_name("relative")
+                .long("relative")
+                .global(true)
+                .help("Display timestamps relative to now (e.g. 5m ago)"),
+        );
+    #[cfg(feature = "tui")]
+    let app = app
+        .arg(
+            Arg::with_name("bell")
+                .long("bell")
+                .help("In the TUI, ring the terminal bell when messages arrive"),
+        )
+        .arg(
+            Arg::with_name("title")
+                .long("title")
+                .help("In the TUI, show the number of unread messages in the terminal title"),
         );
     let app = if host.is_none() {
         app.arg(
@@ -83,33 +160,40 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         );
 
-    let matches = app.clone().get_matches();
+    let matches = match app.clone().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e) if e.use_stderr() => return Err(Error::from(e).context(ErrorKind::Usage).into()),
+        Err(e) => e.exit(),
+    };
+    init_logging(&matches)?;
+    let time_format = time_format(&matches).context(ErrorKind::Usage)?;
+    let no_input = global_flag(&matches, "no-input");
     let password = matches
         .value_of("password")
         .map(|a| a.to_owned())
         .or_else(|| std::env::var("CUPS_PASSWORD").ok())
         .or_else(|| {
-            use std::io::Write;
-            print!("PASSWORD: ");
-            std::io::stdout().flush().unwrap();
-            rpassword::read_password().ok()
+            if no_input {
+                None
+            } else {
+                rpassword::read_password_from_tty(Some("PASSWORD: ")).ok()
+            }
         })
-        .ok_or_else(|| failure::format_err!("requires password"))?;
+        .ok_or_else(|| failure::format_err!("requires password").context(ErrorKind::Auth))?;
     let host: Host = matches
         .value_of("host")
         .map(Host::parse)
-        .transpose()?
+        .transpose()
+        .context(ErrorKind::InvalidAddress)?
         .or(host)
         .unwrap();
     let proxy = match &host {
-        Host::Domain(s) if s.ends_with(".onion") => Some(Proxy::http("socks5h://127.0.0.1:9050")?),
+        Host::Domain(s) if s.ends_with(".onion") => {
+            Some(Proxy::http("socks5h://127.0.0.1:9050").context(ErrorKind::Network)?)
+        }
         _ => proxy,
     };
-    let creds = cupslib::Creds {
-        host,
-        proxy,
-        password,
-    };
+    let creds = cupslib::Creds::new(host, proxy, password)?;
     match matches.subcommand() {
         ("contacts", Some(sub_m)) => match sub_m.subcommand() {
             ("show", _) | ("list", _) | ("ls", _) => {
@@ -121,10 +205,10 @@ async fn inner_main() -> Result<(), Error> {
                     Cell::new("NAME"),
                     Cell::new("UNREADS"),
                 ]));
-                for user in cupslib::fetch_users(&creds).await? {
+                for user in cupslib::fetch_users(&creds).await?.records {
                     table.add_row(Row::new(vec![
                         Cell::new(&cupslib::pubkey_to_onion(&user.id)?),
-                        Cell::new(&user.name.as_ref().map(|a| a.as_str()).unwrap_or("")),
+                        Cell::new(user.name.as_ref().map(Text::as_str).unwrap_or("")),
                         Cell::new(&format!("{}", user.unreads)),
                     ]));
                 }
@@ -156,23 +240,18 @@ async fn inner_main() -> Result<(), Error> {
                 let msgs = cupslib::fetch_messages(
                     &creds,
                     &cupslib::onion_to_pubkey(sub_sub_m.value_of("ADDRESS").unwrap())?,
-                    sub_sub_m.value_of("limit").map(|a| a.parse()).transpose()?,
+                    sub_sub_m
+                        .value_of("limit")
+                        .map(|a| a.parse::<usize>())
+                        .transpose()
+                        .context(ErrorKind::Usage)?,
                 )
                 .await?;
-                for msg in msgs.into_iter().rev() {
+                for msg in msgs.records.into_iter().rev() {
                     table.add_row(Row::new(vec![
                         Cell::new(if msg.inbound { "INBOUND" } else { "OUTBOUND" }),
-                        Cell::new(&format!(
-                            "{}",
-                            chrono::DateTime::<chrono::Local>::from(if msg.time > 0 {
-                                std::time::UNIX_EPOCH
-                                    + std::time::Duration::from_secs(msg.time as u64)
-                            } else {
-                                std::time::UNIX_EPOCH
-                                    - std::time::Duration::from_secs(msg.time.abs() as u64)
-                            })
-                        )),
-                        Cell::new(&msg.content),
+                        Cell::new(&msg.format_time(&time_format)),
+                        Cell::new(msg.content.as_str()),
                     ]));
                 }
                 table.printstd();
@@ -192,7 +271,13 @@ async fn inner_main() -> Result<(), Error> {
         },
         _ => {
             #[cfg(feature = "tui")]
-            tui::tui(creds).await?;
+            {
+                let notify = tui::Notify {
+                    bell: matches.is_present("bell"),
+                    title: matches.is_present("title"),
+                };
+                tui::tui(creds, time_format, notify).await?;
+            }
             #[cfg(not(feature = "tui"))]
             {
                 app.print_long_help()?;
@@ -204,10 +289,141 @@ async fn inner_main() -> Result<(), Error> {
     Ok(())
 }
 
+/// Global args may be given after a subcommand, so check the most specific matches first.
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches
+        .subcommand()
+        .1
+        .and_then(|sub_m| global_value(sub_m, name))
+        .or_else(|| matches.value_of(name))
+}
+
+fn global_flag(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name)
+        || matches
+            .subcommand()
+            .1
+            .map(|sub_m| global_flag(sub_m, name))
+            .unwrap_or(false)
+}
+
+fn global_occurrences(matches: &ArgMatches, name: &str) -> u64 {
+    std::cmp::max(
+        matches.occurrences_of(name),
+        matches
+            .subcommand()
+            .1
+            .map(|sub_m| global_occurrences(sub_m, name))
+            .unwrap_or(0),
+    )
+}
+
+/// Limits dependencies (hyper in particular) to warnings until past -vvv.
+struct Verbosity {
+    ours: LevelFilter,
+    others: LevelFilter,
+}
+impl<S: Subscriber> Layer<S> for Verbosity {
+    fn enabled(&self, meta: &Metadata, _: Context<S>) -> bool {
+        let max = if meta.target().starts_with("cupslib") || meta.target().starts_with("cups_cli") {
+            self.ours
+        } else {
+            self.others
+        };
+        *meta.level() <= max
+    }
+}
+
+fn init_logging(matches: &ArgMatches) -> Result<(), Error> {
+    let verbosity = match global_occurrences(matches, "verbose") {
+        0 => Verbosity {
+            ours: LevelFilter::WARN,
+            others: LevelFilter::WARN,
+        },
+        1 => Verbosity {
+            ours: LevelFilter::INFO,
+            others: LevelFilter::WARN,
+        },
+        2 => Verbosity {
+            ours: LevelFilter::DEBUG,
+            others: LevelFilter::WARN,
+        },
+        3 => Verbosity {
+            ours: LevelFilter::TRACE,
+            others: LevelFilter::WARN,
+        },
+        _ => Verbosity {
+            ours: LevelFilter::TRACE,
+            others: LevelFilter::TRACE,
+        },
+    };
+    let builder = tracing_subscriber::fmt().with_max_level(Level::TRACE);
+    if let Some(path) = global_value(matches, "log-file") {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|_| format!("failed to open log file {}", path))?;
+        tracing::subscriber::set_global_default(
+            builder
+                .with_ansi(false)
+                .with_writer(std::sync::Arc::new(file))
+                .finish()
+                .with(verbosity),
+        )?;
+    } else if matches.subcommand_name().is_some() {
+        // the TUI owns the terminal, so it only logs to a file
+        tracing::subscriber::set_global_default(
+            builder
+                .with_ansi(atty::is(atty::Stream::Stderr))
+                .with_writer(std::io::stderr)
+                .finish()
+                .with(verbosity),
+        )?;
+    }
+    Ok(())
+}
+
+fn time_format(matches: &ArgMatches) -> Result<TimeFormat, Error> {
+    let mut fmt = TimeFormat::default();
+    if let Some(format) = global_value(matches, "time-format") {
+        // chrono only finds a bad format out when it comes to use it, and
+        // then panics
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            failure::bail!("invalid time format: {}", format);
+        }
+        fmt.format = format.to_owned();
+    }
+    if global_flag(matches, "utc") {
+        fmt.zone = Zone::Utc;
+    }
+    if let Some(tz) = global_value(matches, "tz") {
+        fmt.zone = Zone::Named(
+            tz.parse()
+                .map_err(|e: String| failure::format_err!("{}", e))?,
+        );
+    }
+    fmt.relative = global_flag(matches, "relative");
+    Ok(fmt)
+}
+
 #[tokio::main(core_threads = 4)]
 async fn main() {
-    match inner_main().await {
-        Ok(_) => (),
-        Err(e) => println!("{}", e),
+    if let Err(e) = inner_main().await {
+        report(&e);
+        std::process::exit(ErrorKind::of(&e).map(ErrorKind::exit_code).unwrap_or(1));
+    }
+}
+
+fn report(e: &Error) {
+    // clap formats its own errors, including usage
+    if let Some(e) = e.iter_chain().find_map(|f| f.downcast_ref::<clap::Error>()) {
+        eprintln!("{}", e.message);
+        return;
+    }
+    let mut msg = format!("error: {}", e);
+    for cause in e.iter_causes() {
+        msg += &format!(": {}", cause);
     }
+    eprintln!("{}", msg);
 }
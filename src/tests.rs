@@ -0,0 +1,216 @@
+use super::*;
+
+fn decode_error(err: &Error) -> Option<&DecodeError> {
+    err.iter_chain()
+        .find_map(|f| f.downcast_ref::<DecodeError>())
+}
+
+#[tokio::test]
+async fn responses_over_the_limit_are_refused() {
+    let res = |len| rq::Response::from(http::Response::new(vec![0; len]));
+    assert_eq!(read_body(res(16)).await.unwrap().len(), 16);
+    let err = read_body(res(MAX_RESPONSE_SIZE + 1)).await.unwrap_err();
+    assert_eq!(
+        decode_error(&err),
+        Some(&DecodeError::ResponseTooLarge {
+            limit: MAX_RESPONSE_SIZE
+        })
+    );
+    assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Decode));
+}
+
+fn message(inbound: bool, time: i64, content: &[u8]) -> Vec<u8> {
+    let mut rec = vec![inbound as u8];
+    rec.extend_from_slice(&[0; 24]);
+    rec.extend_from_slice(&time.to_be_bytes());
+    rec.extend_from_slice(&(content.len() as u64).to_be_bytes());
+    rec.extend_from_slice(content);
+    rec
+}
+
+fn contents(decoded: &Decoded<Message>) -> Vec<&str> {
+    decoded.records.iter().map(|m| m.content.as_str()).collect()
+}
+
+#[test]
+fn invalid_utf8_is_kept_and_noted() {
+    let mut body = vec![7; 32];
+    body.extend_from_slice(&3u64.to_be_bytes());
+    body.extend_from_slice(&[2, b'b', 0xff]);
+    let decoded = decode(&body, decode_user);
+    assert_eq!(decoded.records.len(), 1);
+    assert_eq!(
+        decoded.records[0].name,
+        Some(Text::Invalid {
+            raw: vec![b'b', 0xff],
+            lossy: "b\u{fffd}".to_owned(),
+        })
+    );
+    assert_eq!(decoded.records[0].unreads, 3);
+    assert_eq!(
+        decoded.warnings,
+        [DecodeWarning {
+            record: 0,
+            error: DecodeError::InvalidUtf8 {
+                field: "name",
+                offset: 41,
+            },
+        }]
+    );
+}
+
+#[test]
+fn oversized_messages_are_skipped_and_the_rest_decoded() {
+    let mut body = message(true, 1, b"hi");
+    body.extend(message(false, 2, &vec![b'x'; MAX_MESSAGE_SIZE + 1]));
+    body.extend(message(true, 3, b"there"));
+    let decoded = decode(&body, decode_message);
+    assert_eq!(contents(&decoded), ["hi", "there"]);
+    assert_eq!(decoded.received, 3);
+    assert_eq!(decoded.records[1].time, 3);
+    assert_eq!(
+        decoded.warnings,
+        [DecodeWarning {
+            record: 1,
+            error: DecodeError::FieldTooLarge {
+                field: "content",
+                offset: 84,
+                len: MAX_MESSAGE_SIZE as u64 + 1,
+                limit: MAX_MESSAGE_SIZE,
+            },
+        }]
+    );
+}
+
+#[test]
+fn truncated_framing_keeps_the_records_before_it() {
+    let mut body = message(true, 1, b"hi");
+    body.extend(message(false, 2, b"yo"));
+    body.extend(&message(true, 3, b"cut off")[..10]);
+    let decoded = decode(&body, decode_message);
+    assert_eq!(contents(&decoded), ["hi", "yo"]);
+    assert_eq!(decoded.received, 3);
+    assert_eq!(
+        decoded.warnings,
+        [DecodeWarning {
+            record: 2,
+            error: DecodeError::UnexpectedEof {
+                field: "reserved",
+                offset: 87,
+                needed: 24,
+                remaining: 9,
+            },
+        }]
+    );
+}
+
+#[test]
+fn relative_times_round_down_to_the_largest_unit() {
+    let fmt = TimeFormat {
+        relative: true,
+        ..TimeFormat::default()
+    };
+    let now = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let cases = [
+        (0, "just now"),
+        (59, "just now"),
+        (-59, "just now"),
+        (60, "1m ago"),
+        (60 * 60 - 1, "59m ago"),
+        (60 * 60, "1h ago"),
+        (60 * 60 * 24 - 1, "23h ago"),
+        (60 * 60 * 24 * 2, "2d ago"),
+        (60 * 60 * 24 * 364, "364d ago"),
+        (60 * 60 * 24 * 365 * 3, "3y ago"),
+        (-60 * 5, "in 5m"),
+        (-60 * 60 * 2, "in 2h"),
+    ];
+    for (ago, expected) in cases.iter() {
+        let time = now - chrono::Duration::seconds(*ago);
+        assert_eq!(fmt.format_at(time, now), *expected, "{}s ago", ago);
+    }
+}
+
+#[test]
+fn absolute_times_are_shown_in_the_chosen_zone() {
+    // the morning the clocks went forward in New York
+    let time = Utc.with_ymd_and_hms(2020, 3, 8, 12, 34, 56).unwrap();
+    let cases = [
+        (
+            Zone::Utc,
+            "%Y-%m-%d %H:%M:%S %:z",
+            "2020-03-08 12:34:56 +00:00",
+        ),
+        (
+            Zone::Named(chrono_tz::America::New_York),
+            "%Y-%m-%d %H:%M:%S %:z",
+            "2020-03-08 08:34:56 -04:00",
+        ),
+        (
+            Zone::Named(chrono_tz::Asia::Kolkata),
+            "%H:%M %Z",
+            "18:04 IST",
+        ),
+        (Zone::Utc, "%a %-d %b, %I:%M %p", "Sun 8 Mar, 12:34 PM"),
+    ];
+    for (zone, format, expected) in cases.iter() {
+        let fmt = TimeFormat {
+            zone: *zone,
+            format: (*format).to_owned(),
+            relative: false,
+        };
+        // a relative format would ignore all of this
+        assert_eq!(fmt.format_at(time, time), *expected);
+    }
+    // the day a message falls on is the zone's, not UTC's
+    let late = Utc.with_ymd_and_hms(2020, 3, 8, 23, 30, 0).unwrap();
+    let tokyo = TimeFormat {
+        zone: Zone::Named(chrono_tz::Asia::Tokyo),
+        ..TimeFormat::default()
+    };
+    assert_eq!(
+        tokyo.date(late),
+        NaiveDate::from_ymd_opt(2020, 3, 9).unwrap()
+    );
+    let utc = TimeFormat {
+        zone: Zone::Utc,
+        ..tokyo
+    };
+    assert_eq!(utc.date(late), NaiveDate::from_ymd_opt(2020, 3, 8).unwrap());
+}
+
+#[test]
+fn each_kind_of_error_has_its_own_exit_code() {
+    let kinds = [
+        (ErrorKind::Usage, 2),
+        (ErrorKind::Auth, 3),
+        (ErrorKind::Network, 4),
+        (ErrorKind::InvalidAddress, 5),
+        (ErrorKind::Decode, 6),
+    ];
+    for (kind, code) in kinds.iter() {
+        assert_eq!(kind.exit_code(), *code, "{}", kind);
+        let err: Error = failure::err_msg("cause").context(*kind).into();
+        assert_eq!(ErrorKind::of(&err), Some(*kind));
+    }
+    assert_eq!(ErrorKind::of(&failure::err_msg("no kind")), None);
+}
+
+#[test]
+fn rejected_passwords_are_auth_errors() {
+    let kind = |status| check_status(status).err().map(|e| ErrorKind::of(&e));
+    assert_eq!(kind(rq::StatusCode::OK), None);
+    assert_eq!(kind(rq::StatusCode::NO_CONTENT), None);
+    assert_eq!(
+        kind(rq::StatusCode::UNAUTHORIZED),
+        Some(Some(ErrorKind::Auth))
+    );
+    assert_eq!(
+        kind(rq::StatusCode::FORBIDDEN),
+        Some(Some(ErrorKind::Network))
+    );
+    assert_eq!(
+        kind(rq::StatusCode::INTERNAL_SERVER_ERROR),
+        Some(Some(ErrorKind::Network))
+    );
+}
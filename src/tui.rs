@@ -1,321 +1,124 @@
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
-use failure::Error;
-use futures::poll;
-use pancurses::*;
+use failure::{Error, ResultExt};
+use tokio::sync::mpsc;
 
-use cupslib::{Creds, Message, Pubkey, UserData};
+use cupslib::{Creds, Pubkey, TimeFormat};
 
-pub struct Windows {
-    main: Window,
-    sidebar: Window,
-    topbar: Window,
-    feed: Window,
-    input: Window,
-}
+mod backend;
+mod command;
+mod composer;
+mod config;
+mod dialog;
+mod drafts;
+mod filter;
+mod keymap;
+mod search;
+mod state;
+mod text;
+mod theme;
+mod ui;
+
+use keymap::Keymap;
+pub use state::Notify;
+use state::{Effect, Event, State};
+use theme::Theme;
 
-unsafe impl std::marker::Send for Windows {}
-unsafe impl std::marker::Sync for Windows {}
+/// How often the contact list and the open conversation are re-fetched.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
 
-pub async fn tui(creds: Creds) -> Result<(), Error> {
-    let main = initscr();
-    let sidebar = main
-        .subwin(main.get_max_y(), main.get_max_x() / 4, 0, 0)
-        .unwrap();
-    let topbar = main
-        .subwin(
-            main.get_max_y() / 8,
-            main.get_max_x() * 3 / 4,
-            0,
-            main.get_max_x() / 4,
-        )
-        .unwrap();
-    let feed = main
-        .subwin(
-            main.get_max_y() * 5 / 8,
-            main.get_max_x() * 3 / 4,
-            main.get_max_y() / 8,
-            main.get_max_x() / 4,
-        )
-        .unwrap();
-    let input = main
-        .subwin(
-            main.get_max_y() / 4,
-            main.get_max_x() * 3 / 4,
-            main.get_max_y() * 3 / 4,
-            main.get_max_x() / 4,
-        )
-        .unwrap();
-    let creds = Arc::new(creds);
-    let res = tui_inner(
-        Arc::new(Windows {
-            main,
-            sidebar,
-            topbar,
-            feed,
-            input,
-        }),
-        creds,
-    )
-    .await;
-    endwin();
+pub async fn tui(creds: Creds, time_format: TimeFormat, notify: Notify) -> Result<(), Error> {
+    // a broken keymap, theme or drafts file is reported before the terminal
+    // is taken over
+    let keymap = Keymap::load()?;
+    let theme = Theme::load()?;
+    let drafts = drafts::load()?;
+    let (send, ui_events) = mpsc::unbounded_channel();
+    let (render, ui) = ui::spawn(send, theme).context("starting the terminal")?;
+    let mut state = State::new(time_format, keymap);
+    state.notify = notify;
+    state.load_drafts(drafts);
+    let res = tui_inner(ui_events, render, Arc::new(creds), state).await;
+    // the UI thread restores the terminal once the render channel closes
+    let _ = ui.join();
     res
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum Selected {
-    Sidebar,
-    Input,
-    Feed,
-    Namebar,
-}
-impl Selected {
-    pub fn rotate(&mut self) {
-        match *self {
-            Selected::Sidebar => {
-                *self = Selected::Input;
-            }
-            Selected::Input => {
-                *self = Selected::Feed;
-            }
-            Selected::Feed => {
-                *self = Selected::Namebar;
-            }
-            Selected::Namebar => {
-                *self = Selected::Sidebar;
+/// Feeds terminal input, network results and refresh ticks through the
+/// state, then carries out the effects it asks for.
+async fn tui_inner(
+    mut ui: mpsc::UnboundedReceiver<Event>,
+    render: ui::Renderer,
+    creds: Arc<Creds>,
+    mut state: State,
+) -> Result<(), Error> {
+    let (send, mut events) = mpsc::unbounded_channel();
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        let event = tokio::select! {
+            event = ui.recv() => match event {
+                Some(event) => event,
+                // the UI thread has gone, along with the terminal
+                None => break,
+            },
+            Some(event) = events.recv() => event,
+            _ = refresh.tick() => Event::Tick,
+        };
+        for effect in state.update(event) {
+            match effect {
+                // if the UI thread has gone, `ui` closes too
+                Effect::Render(cmd) => render.send(cmd),
+                // saved here rather than in a task, so it is done before
+                // quitting
+                Effect::SaveDrafts(drafts) => {
+                    let _ = send.send(Event::DraftsSaved(drafts::save(&drafts)));
+                }
+                Effect::Quit => return Ok(()),
+                effect => spawn_request(effect, &creds, &send),
             }
         }
     }
+    Ok(())
 }
 
-pub struct State<
-    U: Fn() -> UF,
-    UF: Future<Output = Result<Vec<UserData>, Error>>,
-    M: Fn(Pubkey) -> MF,
-    MF: Future<Output = Result<Vec<Message>, Error>>,
-> {
-    window: Arc<Windows>,
-    pub user_data: Vec<UserData>,
-    user_future: Option<Pin<Box<UF>>>,
-    user_fn: U,
-    pub messages: Vec<Message>,
-    message_future: Option<Pin<Box<MF>>>,
-    message_fn: M,
-    pub selected: usize,
-}
-impl<U, UF, M, MF> State<U, UF, M, MF>
-where
-    U: Fn() -> UF,
-    UF: Future<Output = Result<Vec<UserData>, Error>>,
-    M: Fn(Pubkey) -> MF,
-    MF: Future<Output = Result<Vec<Message>, Error>>,
-{
-    pub async fn new(window: Arc<Windows>, u: U, m: M) -> Result<Self, Error> {
-        Ok(State {
-            window,
-            user_data: u().await?,
-            user_future: Some(Box::pin(u())),
-            user_fn: u,
-            messages: Vec::new(),
-            message_future: None,
-            message_fn: m,
-            selected: 0,
-        })
-    }
-    pub async fn update(&mut self) -> Result<(), Error> {
-        use std::task::Poll;
-        if let Some(mut uf) = self.user_future.take() {
-            match poll!(uf.as_mut()) {
-                Poll::Ready(s) => {
-                    self.user_future = Some(Box::pin((self.user_fn)()));
-                    let clear = self.user_data.len();
-                    self.user_data = s?;
-                    render_sidebar(&*self.window, &self.user_data, clear, self.selected);
-                }
-                Poll::Pending => self.user_future = Some(uf),
+/// Starts the network request an effect asks for, delivering the result
+/// back to the event loop. Exports also write the file.
+fn spawn_request(effect: Effect, creds: &Arc<Creds>, events: &mpsc::UnboundedSender<Event>) {
+    let creds = creds.clone();
+    let events = events.clone();
+    tokio::spawn(async move {
+        let event = match effect {
+            Effect::FetchUsers => Event::Users(cupslib::fetch_users(creds).await),
+            Effect::FetchMessages { id, fetch, limit } => {
+                let res = cupslib::fetch_messages(creds, Pubkey(id), Some(limit)).await;
+                Event::Messages(id, fetch, res)
             }
-        } else {
-            self.user_future = Some(Box::pin((self.user_fn)()));
-        }
-        if let Some(user) = self.user_data.get(self.selected) {
-            if let Some(mut mf) = self.message_future.take() {
-                match poll!(mf.as_mut()) {
-                    Poll::Ready(s) => {
-                        self.message_future = Some(Box::pin((self.message_fn)(Pubkey(user.id))));
-                        self.messages = s?;
-                        render_feed(&*self.window, &self.messages);
-                    }
-                    Poll::Pending => self.message_future = Some(mf),
-                }
-            } else {
-                self.message_future = Some(Box::pin((self.message_fn)(Pubkey(user.id))));
+            Effect::Send { seq, id, content } => {
+                Event::Sent(seq, cupslib::send_message(&creds, &id, &content).await)
             }
-        }
-        Ok(())
-    }
-}
-
-async fn getch(win: Arc<Windows>) -> Result<Option<Input>, Error> {
-    tokio::task::spawn_blocking(move || win.main.getch())
-        .await
-        .map_err(Error::from)
-}
-
-async fn tui_inner(win: Arc<Windows>, creds: Arc<Creds>) -> Result<(), Error> {
-    let ucreds = creds.clone();
-    let mcreds = creds.clone();
-    let mut state = State::new(
-        win.clone(),
-        move || cupslib::fetch_users(ucreds.clone()),
-        move |id| cupslib::fetch_messages(mcreds.clone(), id, None),
-    )
-    .await?;
-    init(&*win);
-    let mut selected = Selected::Sidebar;
-    let mut chfut = Box::pin(getch(win.clone()));
-    loop {
-        use std::task::Poll;
-        match poll!(chfut.as_mut()) {
-            Poll::Ready(i) => {
-                match i? {
-                    Some(Input::KeyResize)
-                    | Some(Input::KeyAbort)
-                    | Some(Input::Character('q')) => break,
-                    Some(Input::KeySTab) | Some(Input::Character('\t')) => {
-                        hide_selection(&*win, selected);
-                        selected.rotate();
-                        show_selection(&*win, selected);
-                    }
-                    Some(Input::KeyUp) => match selected {
-                        Selected::Sidebar => {
-                            if state.selected > 0 {
-                                state.selected -= 1;
-                                change_sidebar_selection(
-                                    &*win,
-                                    &state.user_data,
-                                    state.selected,
-                                    state.selected + 1,
-                                )
-                            }
-                        }
-                        _ => (),
-                    },
-                    _ => (),
-                };
-                chfut = Box::pin(getch(win.clone()));
+            Effect::Save { onion, name } => {
+                Event::Saved(cupslib::add_user(&creds, &onion, &name).await)
             }
-            Poll::Pending => (),
-        }
-        // state.update().await?;
-    }
-    Ok(())
-}
-
-fn init(win: &Windows) {
-    resize_term(0, 0);
-    curs_set(0);
-    noecho();
-    win.main.nodelay(true);
-    win.main.clear();
-    win.main.border('|', '|', '-', '-', '+', '+', '+', '+');
-    win.sidebar.attron(Attribute::Bold);
-    win.sidebar.border('|', '|', '-', '-', '+', '+', '+', '+');
-    win.sidebar.attroff(Attribute::Bold);
-    win.topbar.border('|', '|', '-', '-', '+', '+', '+', '+');
-    win.feed.border('|', '|', '-', '-', '+', '+', '+', '+');
-    win.input.border('|', '|', '-', '-', '+', '+', '+', '+');
-    win.main.refresh();
-}
-
-fn show_selection(win: &Windows, sel: Selected) {
-    use Selected::*;
-    match sel {
-        Sidebar => {
-            win.sidebar.attron(Attribute::Bold);
-            win.sidebar.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.sidebar.attroff(Attribute::Bold);
-            win.sidebar.refresh();
-        }
-        Namebar => {
-            win.topbar.attron(Attribute::Bold);
-            win.topbar.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.topbar.attroff(Attribute::Bold);
-            win.topbar.refresh();
-        }
-        Feed => {
-            win.feed.attron(Attribute::Bold);
-            win.feed.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.feed.attroff(Attribute::Bold);
-            win.feed.refresh();
-        }
-        Input => {
-            win.input.attron(Attribute::Bold);
-            win.input.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.input.attroff(Attribute::Bold);
-            win.input.refresh();
-        }
-    }
-    win.main.refresh();
-}
-
-fn hide_selection(win: &Windows, sel: Selected) {
-    use Selected::*;
-    match sel {
-        Sidebar => {
-            win.sidebar.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.sidebar.refresh();
-        }
-        Namebar => {
-            win.topbar.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.topbar.refresh();
-        }
-        Feed => {
-            win.feed.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.feed.refresh();
-        }
-        Input => {
-            win.input.border('|', '|', '-', '-', '+', '+', '+', '+');
-            win.input.refresh();
-        }
-    }
-}
-
-fn render_sidebar(win: &Windows, data: &[UserData], clear: usize, selected: usize) {
-    if data.len() < clear {
-        for i in data.len()..clear {
-            win.sidebar.mv(i as i32 + 4, 2);
-            win.sidebar.clrtoeol();
-        }
-    }
-    for (i, user) in data.iter().enumerate() {
-        win.sidebar.mv(i as i32 + 2, 2);
-        if i == selected {
-            win.sidebar.attron(Attribute::Reverse);
-        }
-        win.sidebar.clrtoeol();
-        if let Some(name) = &user.name {
-            win.sidebar.addstr(&format!(
-                "{:54} {}",
-                &name[..std::cmp::min(name.len(), 54)],
-                user.unreads
-            ));
-        } else {
-            win.sidebar.addstr(&format!(
-                "{} {}",
-                base32::encode(base32::Alphabet::RFC4648 { padding: false }, &user.id)
-                    .to_lowercase(),
-                user.unreads
-            ));
-        }
-        if i == selected {
-            win.sidebar.attroff(Attribute::Reverse);
-        }
-    }
-    win.sidebar.refresh();
+            Effect::Export {
+                id,
+                name,
+                path,
+                time_format,
+            } => {
+                let res = cupslib::fetch_messages(creds, Pubkey(id), None).await;
+                Event::Exported(res.and_then(|msgs| {
+                    // the server sends newest first
+                    let mut messages = msgs.records;
+                    messages.reverse();
+                    let text = command::transcript(&messages, &name, &time_format);
+                    std::fs::write(&path, text)
+                        .with_context(|_| format!("writing {}", path.display()))?;
+                    Ok((path, messages.len()))
+                }))
+            }
+            Effect::Render(_) | Effect::SaveDrafts(_) | Effect::Quit => return,
+        };
+        let _ = events.send(event);
+    });
 }
-
-fn change_sidebar_selection(win: &Windows, data: &[UserData], selected: usize, clear: usize) {}
-
-fn render_feed(win: &Windows, msgs: &[Message]) {}
@@ -0,0 +1,230 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use failure::{format_err, Error, ResultExt};
+use pancurses::{Attributes, Window};
+
+use super::state::Rect;
+
+#[cfg(test)]
+pub mod memory;
+
+/// A rectangle of the screen that can be drawn on. Positions are relative
+/// to its top left corner. Drawing only shows once the backend is flushed.
+pub trait Pane {
+    /// Rows and columns.
+    fn get_max_yx(&self) -> (i32, i32);
+    fn mv(&self, y: i32, x: i32);
+    fn addstr<T: AsRef<str>>(&self, text: T);
+    fn mvaddstr<T: AsRef<str>>(&self, y: i32, x: i32, text: T) {
+        self.mv(y, x);
+        self.addstr(text);
+    }
+    fn attron(&self, attrs: Attributes);
+    fn attroff(&self, attrs: Attributes);
+    /// Blanks the whole pane.
+    fn erase(&self);
+    /// Draws a box around the edge of the pane.
+    fn draw_border(&self, borders: Borders);
+    /// Marks what was drawn to be shown on the next flush.
+    fn noutrefresh(&self);
+}
+
+/// What borders are drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Borders {
+    Ascii,
+    /// line drawing characters
+    Unicode,
+}
+impl Borders {
+    /// Line drawing characters if the locale is UTF-8, since they may not
+    /// show otherwise.
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        if locale.contains("utf-8") || locale.contains("utf8") {
+            Borders::Unicode
+        } else {
+            Borders::Ascii
+        }
+    }
+}
+
+/// Something that can be drawn on: the terminal, or a grid in memory.
+pub trait Backend {
+    type Pane: Pane;
+    /// Rows and columns.
+    fn size(&self) -> (i32, i32);
+    /// A pane covering `rect`. Panes may overlap, and whatever was drawn
+    /// last shows.
+    fn pane(&self, rect: Rect) -> Option<Self::Pane>;
+    /// Blanks the whole screen, so it is drawn again from scratch.
+    fn clear(&self);
+    /// Shows everything drawn since the last flush.
+    fn flush(&self);
+    fn bell(&self);
+    /// Keeps the terminal's title, for `restore_title` to put back.
+    fn save_title(&self);
+    fn set_title(&self, title: &str);
+    fn restore_title(&self);
+    /// Hands the terminal to the user's editor on a file holding `text`,
+    /// returning what the file holds once the editor exits.
+    fn edit(&self, text: &str) -> Result<String, Error>;
+}
+
+impl Pane for Window {
+    fn get_max_yx(&self) -> (i32, i32) {
+        Window::get_max_yx(self)
+    }
+    fn mv(&self, y: i32, x: i32) {
+        Window::mv(self, y, x);
+    }
+    fn addstr<T: AsRef<str>>(&self, text: T) {
+        Window::addstr(self, text);
+    }
+    fn attron(&self, attrs: Attributes) {
+        Window::attron(self, attrs);
+    }
+    fn attroff(&self, attrs: Attributes) {
+        Window::attroff(self, attrs);
+    }
+    fn erase(&self) {
+        Window::erase(self);
+    }
+    fn draw_border(&self, borders: Borders) {
+        match borders {
+            Borders::Ascii => self.border('|', '|', '-', '-', '+', '+', '+', '+'),
+            // curses' own line drawing characters
+            Borders::Unicode => self.draw_box(0, 0),
+        };
+    }
+    fn noutrefresh(&self) {
+        Window::noutrefresh(self);
+    }
+}
+
+/// The terminal, through the curses root window. Panes are subwindows of it.
+impl Backend for Window {
+    type Pane = Window;
+    fn size(&self) -> (i32, i32) {
+        self.get_max_yx()
+    }
+    fn pane(&self, rect: Rect) -> Option<Window> {
+        self.subwin(rect.rows, rect.cols, rect.y, rect.x).ok()
+    }
+    fn clear(&self) {
+        Window::clear(self);
+        Window::noutrefresh(self);
+    }
+    fn flush(&self) {
+        pancurses::doupdate();
+    }
+    fn bell(&self) {
+        pancurses::beep();
+    }
+    // curses has no say over the title, so these go straight to the
+    // terminal: xterm's title stack, and the title escape
+    fn save_title(&self) {
+        write_escape("\x1b[22;0t");
+    }
+    fn set_title(&self, title: &str) {
+        let title: String = title.chars().filter(|c| !c.is_control()).collect();
+        write_escape(&format!("\x1b]2;{}\x07", title));
+    }
+    fn restore_title(&self) {
+        write_escape("\x1b[23;0t");
+    }
+    fn edit(&self, text: &str) -> Result<String, Error> {
+        let (temp, mut file) = TempFile::create()?;
+        let path = &temp.0;
+        file.write_all(text.as_bytes())
+            .with_context(|_| format!("writing {}", path.display()))?;
+        drop(file);
+        let editor = editor();
+        pancurses::endwin();
+        // through the shell, so the editor can come with arguments
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(path)
+            .status();
+        // curses takes the terminal back on the next refresh
+        self.refresh();
+        match status {
+            Ok(status) if status.success() => std::fs::read_to_string(path)
+                .with_context(|_| format!("reading {}", path.display()))
+                .map_err(Error::from),
+            Ok(status) => Err(format_err!("`{}` exited with {}", editor, status)),
+            Err(e) => Err(Error::from(e)
+                .context(format!("running `{}`", editor))
+                .into()),
+        }
+    }
+}
+
+/// A file for the editor to work on, which only this user can read and
+/// which is removed when dropped, however editing ends.
+struct TempFile(PathBuf);
+impl TempFile {
+    /// Creates the file under a name no one else can guess, so nothing
+    /// planted there beforehand is written through.
+    fn create() -> Result<(TempFile, File), Error> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut tries = 0;
+        loop {
+            let path = std::env::temp_dir().join(format!("cups-{:016x}.txt", random()));
+            match options.open(&path) {
+                Ok(file) => return Ok((TempFile(path), file)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && tries < 8 => tries += 1,
+                Err(e) => {
+                    return Err(Error::from(e)
+                        .context(format!("creating {}", path.display()))
+                        .into())
+                }
+            }
+        }
+    }
+}
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A number that differs from call to call and run to run, from the random
+/// keys std seeds its hash maps with.
+fn random() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+/// `$VISUAL`, or else `$EDITOR`, or else vi.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_owned())
+}
+
+fn write_escape(escape: &str) {
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(escape.as_bytes());
+    let _ = stdout.flush();
+}
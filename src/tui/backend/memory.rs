@@ -0,0 +1,256 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use failure::{err_msg, Error};
+use pancurses::Attributes;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{Backend, Borders, Pane};
+use crate::tui::state::Rect;
+use crate::tui::text;
+
+/// One cell of the grid. A double width grapheme is stored in the cell it
+/// starts in, and the cell after it is left empty.
+#[derive(Clone, Debug, Default)]
+struct Glyph {
+    text: String,
+    attrs: Vec<Attributes>,
+}
+
+struct Grid {
+    rows: i32,
+    cols: i32,
+    cells: Vec<Vec<Glyph>>,
+}
+impl Grid {
+    fn new(rows: i32, cols: i32) -> Self {
+        let blank = Glyph {
+            text: " ".to_owned(),
+            attrs: Vec::new(),
+        };
+        Grid {
+            rows,
+            cols,
+            cells: vec![vec![blank; cols as usize]; rows as usize],
+        }
+    }
+}
+
+/// Stands in for the user's editor, turning the text it is given into what
+/// is left in the file.
+type Editor = fn(&str) -> String;
+
+/// A screen held in memory, so what the TUI draws can be checked in tests.
+/// Unlike the terminal, what is drawn shows straight away.
+#[derive(Clone)]
+pub struct MemoryScreen {
+    grid: Rc<RefCell<Grid>>,
+    bells: Rc<Cell<usize>>,
+    /// titles saved to be put back, then the one shown, which starts out
+    /// empty
+    titles: Rc<RefCell<Vec<String>>>,
+    /// what the editor does to the text it is given, if there is one
+    editor: Rc<Cell<Option<Editor>>>,
+}
+impl MemoryScreen {
+    pub fn new(rows: i32, cols: i32) -> Self {
+        MemoryScreen {
+            grid: Rc::new(RefCell::new(Grid::new(rows, cols))),
+            bells: Rc::new(Cell::new(0)),
+            titles: Rc::new(RefCell::new(vec![String::new()])),
+            editor: Rc::new(Cell::new(None)),
+        }
+    }
+    /// Sets what editing does to the text, which fails until this is set.
+    pub fn set_editor(&self, editor: Editor) {
+        self.editor.set(Some(editor));
+    }
+    /// How many times the bell has rung.
+    pub fn bells(&self) -> usize {
+        self.bells.get()
+    }
+    /// The title shown, if any.
+    pub fn title(&self) -> Option<String> {
+        let titles = self.titles.borrow();
+        titles.last().filter(|title| !title.is_empty()).cloned()
+    }
+    /// Changes the size, blanking the screen.
+    pub fn resize(&self, rows: i32, cols: i32) {
+        *self.grid.borrow_mut() = Grid::new(rows, cols);
+    }
+    /// The text on screen, one line per row with trailing blanks trimmed.
+    pub fn contents(&self) -> String {
+        let grid = self.grid.borrow();
+        let lines: Vec<String> = grid
+            .cells
+            .iter()
+            .map(|row| {
+                let line: String = row.iter().map(|g| g.text.as_str()).collect();
+                line.trim_end().to_owned()
+            })
+            .collect();
+        lines.join("\n")
+    }
+    /// The attributes on screen, one character per cell: R for reverse,
+    /// B for bold, D for dim, U for underline and . for none. Where a cell
+    /// has several, the first in that order is shown. Colors are left out.
+    pub fn attributes(&self) -> String {
+        let grid = self.grid.borrow();
+        let lines: Vec<String> = grid
+            .cells
+            .iter()
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .map(|g| {
+                        let has = |is: fn(&Attributes) -> bool| g.attrs.iter().any(is);
+                        if has(Attributes::is_reverse) {
+                            'R'
+                        } else if has(Attributes::is_bold) {
+                            'B'
+                        } else if has(Attributes::is_dim) {
+                            'D'
+                        } else if has(Attributes::is_underline) {
+                            'U'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                line.trim_end_matches('.').to_owned()
+            })
+            .collect();
+        lines.join("\n")
+    }
+    /// The text and then the attributes, for comparing against a snapshot.
+    pub fn snapshot(&self) -> String {
+        format!("{}\n---\n{}", self.contents(), self.attributes())
+    }
+}
+
+impl Backend for MemoryScreen {
+    type Pane = MemoryPane;
+    fn size(&self) -> (i32, i32) {
+        let grid = self.grid.borrow();
+        (grid.rows, grid.cols)
+    }
+    fn pane(&self, rect: Rect) -> Option<MemoryPane> {
+        let (rows, cols) = self.size();
+        if rect.y < 0 || rect.x < 0 || rect.y + rect.rows > rows || rect.x + rect.cols > cols {
+            return None;
+        }
+        Some(MemoryPane {
+            grid: self.grid.clone(),
+            rect,
+            cursor: Cell::new((0, 0)),
+            attrs: RefCell::new(Vec::new()),
+        })
+    }
+    fn clear(&self) {
+        let (rows, cols) = self.size();
+        self.resize(rows, cols);
+    }
+    fn flush(&self) {}
+    fn bell(&self) {
+        self.bells.set(self.bells.get() + 1);
+    }
+    fn save_title(&self) {
+        let mut titles = self.titles.borrow_mut();
+        let shown = titles.last().cloned().unwrap_or_default();
+        titles.push(shown);
+    }
+    fn set_title(&self, title: &str) {
+        if let Some(shown) = self.titles.borrow_mut().last_mut() {
+            *shown = title.to_owned();
+        }
+    }
+    fn restore_title(&self) {
+        let mut titles = self.titles.borrow_mut();
+        if titles.len() > 1 {
+            titles.pop();
+        }
+    }
+    fn edit(&self, text: &str) -> Result<String, Error> {
+        match self.editor.get() {
+            Some(editor) => Ok(editor(text)),
+            None => Err(err_msg("no editor")),
+        }
+    }
+}
+
+pub struct MemoryPane {
+    grid: Rc<RefCell<Grid>>,
+    rect: Rect,
+    cursor: Cell<(i32, i32)>,
+    attrs: RefCell<Vec<Attributes>>,
+}
+
+impl Pane for MemoryPane {
+    fn get_max_yx(&self) -> (i32, i32) {
+        (self.rect.rows, self.rect.cols)
+    }
+    fn mv(&self, y: i32, x: i32) {
+        self.cursor.set((y, x));
+    }
+    /// Writes `text` from the cursor, cutting it off at the edge of the pane
+    /// rather than wrapping.
+    fn addstr<T: AsRef<str>>(&self, text: T) {
+        let text = text.as_ref();
+        let (y, mut x) = self.cursor.get();
+        if y < 0 || y >= self.rect.rows || x < 0 {
+            return;
+        }
+        let mut grid = self.grid.borrow_mut();
+        let row = &mut grid.cells[(self.rect.y + y) as usize];
+        let attrs = self.attrs.borrow();
+        for grapheme in text.graphemes(true) {
+            let width = std::cmp::max(text::grapheme_width(grapheme), 1) as i32;
+            if x + width > self.rect.cols {
+                break;
+            }
+            let at = (self.rect.x + x) as usize;
+            row[at] = Glyph {
+                text: grapheme.to_owned(),
+                attrs: attrs.clone(),
+            };
+            for cell in row.iter_mut().skip(at + 1).take(width as usize - 1) {
+                *cell = Glyph {
+                    text: String::new(),
+                    attrs: attrs.clone(),
+                };
+            }
+            x += width;
+        }
+        self.cursor.set((y, x));
+    }
+    fn attron(&self, attrs: Attributes) {
+        let mut on = self.attrs.borrow_mut();
+        if !on.contains(&attrs) {
+            on.push(attrs);
+        }
+    }
+    fn attroff(&self, attrs: Attributes) {
+        self.attrs.borrow_mut().retain(|&a| a != attrs);
+    }
+    fn erase(&self) {
+        let blank = " ".repeat(self.rect.cols as usize);
+        for y in 0..self.rect.rows {
+            self.mvaddstr(y, 0, &blank);
+        }
+    }
+    fn draw_border(&self, borders: Borders) {
+        let [v, h, tl, tr, bl, br] = match borders {
+            Borders::Ascii => ["|", "-", "+", "+", "+", "+"],
+            Borders::Unicode => ["│", "─", "┌", "┐", "└", "┘"],
+        };
+        let (rows, cols) = (self.rect.rows, self.rect.cols);
+        let middle = h.repeat(std::cmp::max(cols - 2, 0) as usize);
+        self.mvaddstr(0, 0, format!("{}{}{}", tl, middle, tr));
+        for y in 1..rows - 1 {
+            self.mvaddstr(y, 0, v);
+            self.mvaddstr(y, cols - 1, v);
+        }
+        self.mvaddstr(rows - 1, 0, format!("{}{}{}", bl, middle, br));
+    }
+    fn noutrefresh(&self) {}
+}
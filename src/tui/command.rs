@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use failure::{bail, format_err, Error};
+use pancurses::Input;
+
+use cupslib::{Message, TimeFormat};
+
+use super::composer::{Composer, History};
+
+/// The commands the prompt knows, in the order completion offers them.
+pub const NAMES: [&str; 6] = ["add", "open", "export", "search", "profile", "quit"];
+
+/// A line typed at the `:` prompt.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// adds a contact, or renames it if it already exists
+    Add {
+        onion: String,
+        name: String,
+    },
+    /// opens the conversation with the contact best matching the name
+    Open(String),
+    /// writes the whole of the open conversation to a file
+    Export(PathBuf),
+    /// searches the feed for the text, as `/` does there
+    Search(String),
+    /// shows the details of the contact best matching the name
+    Profile(String),
+    Quit,
+}
+impl Command {
+    /// Parses a line typed after the `:`. A command can be shortened to
+    /// anything it is the only one to start with, so `:q` quits.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let (name, arg) = split(line);
+        let name = command_name(name).ok_or_else(|| format_err!("unknown command `{}`", name))?;
+        let required = |what: &str| {
+            if arg.is_empty() {
+                Err(format_err!("usage: :{} <{}>", name, what))
+            } else {
+                Ok(arg.to_owned())
+            }
+        };
+        Ok(match name {
+            "add" => match split(arg) {
+                (onion, name) if !name.is_empty() => Command::Add {
+                    onion: onion.to_owned(),
+                    name: name.to_owned(),
+                },
+                _ => bail!("usage: :add <onion> <name>"),
+            },
+            "open" => Command::Open(required("name")?),
+            "export" => Command::Export(expand_home(&required("file")?)),
+            "search" => Command::Search(required("text")?),
+            "profile" => Command::Profile(required("name")?),
+            _ if !arg.is_empty() => bail!("`:quit` takes nothing after it"),
+            _ => Command::Quit,
+        })
+    }
+}
+
+/// Splits off the first word of `line`, trimming what is left.
+fn split(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// The command that `name` is the whole of, or the start of no other.
+fn command_name(name: &str) -> Option<&'static str> {
+    let mut names = NAMES.iter().filter(|n| n.starts_with(name));
+    match (names.next(), names.next()) {
+        (Some(&n), None) if !name.is_empty() => Some(n),
+        _ => None,
+    }
+}
+
+/// `~/` at the start of a path stands for the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// A conversation as plain text, oldest message first: each message under
+/// its time and who sent it, indented.
+pub fn transcript(messages: &[Message], name: &str, time_format: &TimeFormat) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        let from = if msg.inbound { name } else { "me" };
+        out.push_str(&format!("{} {}\n", msg.format_time(time_format), from));
+        for line in msg.content.as_str().lines() {
+            out.push_str(&format!("    {}\n", line));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Candidates for the word at the end of the prompt's line, from the
+/// last time Tab was pressed.
+#[derive(Clone, Debug)]
+struct Completion {
+    /// the line up to the word being completed
+    start: String,
+    candidates: Vec<String>,
+    /// the candidate in the line, once Tab has gone past what they share
+    shown: Option<usize>,
+    /// the line as completion left it, so another Tab carries on
+    line: String,
+}
+
+/// The `:` prompt: the line being typed, where browsing the history is up
+/// to, and what completion last offered.
+#[derive(Clone, Debug, Default)]
+pub struct Prompt {
+    pub line: Composer,
+    /// set when the line is a search of the feed rather than a command
+    pub search: bool,
+    history: History,
+    completion: Option<Completion>,
+}
+impl Prompt {
+    /// The `/` prompt, for searching the feed.
+    pub fn search() -> Self {
+        Prompt {
+            search: true,
+            ..Prompt::default()
+        }
+    }
+    /// Applies an editing key. Returns whether the key was one.
+    pub fn edit(&mut self, key: Input) -> bool {
+        let edited = self.line.edit(key);
+        if edited {
+            self.completion = None;
+        }
+        edited
+    }
+    /// What the last Tab offered, and which of it is in the line.
+    pub fn candidates(&self) -> (&[String], Option<usize>) {
+        match &self.completion {
+            Some(completion) => (&completion.candidates, completion.shown),
+            None => (&[], None),
+        }
+    }
+    /// Puts an earlier command from `history`, oldest first, in the line,
+    /// or a later one if `older` is not set.
+    pub fn browse(&mut self, history: &[String], older: bool) {
+        if self.history.browse(&mut self.line, history, older) {
+            self.completion = None;
+        }
+    }
+    /// Completes the word at the end of the line: a command name, or a
+    /// contact's name after `:open` or `:profile`. Where several
+    /// candidates fit, the first Tab goes as far as they agree and the
+    /// ones after that cycle through them.
+    pub fn complete(&mut self, contacts: &[String]) {
+        let text = self.line.text().to_owned();
+        if let Some(completion) = &mut self.completion {
+            if completion.line == text {
+                let count = completion.candidates.len();
+                let shown = completion.shown.map_or(0, |i| (i + 1) % count);
+                completion.shown = Some(shown);
+                completion.line = format!("{}{}", completion.start, completion.candidates[shown]);
+                return self.line.set(completion.line.clone());
+            }
+        }
+        self.completion = None;
+        let (start, word, candidates, after) = match text.trim_start().find(char::is_whitespace) {
+            None => {
+                let word = text.trim_start();
+                let names = NAMES.iter().filter(|n| n.starts_with(word));
+                let names = names.map(|&n| n.to_owned()).collect();
+                (&text[..text.len() - word.len()], word, names, " ")
+            }
+            Some(_) => {
+                let (name, _) = split(&text);
+                if !matches!(command_name(name), Some("open") | Some("profile")) {
+                    return;
+                }
+                let word = text.trim_start();
+                let word = word.trim_start_matches(|c: char| !c.is_whitespace());
+                let word = word.trim_start();
+                let lower = word.to_lowercase();
+                let mut names: Vec<String> = Vec::new();
+                for contact in contacts {
+                    if contact.to_lowercase().starts_with(&lower) && !names.contains(contact) {
+                        names.push(contact.clone());
+                    }
+                }
+                (&text[..text.len() - word.len()], word, names, "")
+            }
+        };
+        let line = match candidates.len() {
+            0 => return,
+            1 => format!("{}{}{}", start, candidates[0], after),
+            _ => {
+                let common = common_prefix(&candidates);
+                let shown = if common.chars().count() > word.chars().count() {
+                    None
+                } else {
+                    Some(0)
+                };
+                let line = format!("{}{}", start, shown.map_or(common, |i| &candidates[i]));
+                self.completion = Some(Completion {
+                    start: start.to_owned(),
+                    candidates,
+                    shown,
+                    line: line.clone(),
+                });
+                line
+            }
+        };
+        self.line.set(line);
+    }
+}
+
+/// The start all of `words` share, ignoring case, as the first spells it.
+fn common_prefix(words: &[String]) -> &str {
+    let first = &words[0];
+    let mut end = first.len();
+    for word in &words[1..] {
+        let same = first
+            .char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a.to_lowercase().ne(b.to_lowercase()))
+            .map_or(std::cmp::min(first.len(), word.len()), |((i, _), _)| i);
+        end = std::cmp::min(end, same);
+    }
+    let mut end = std::cmp::min(end, first.len());
+    while !first.is_char_boundary(end) {
+        end -= 1;
+    }
+    &first[..end]
+}
+
+#[cfg(test)]
+mod tests;
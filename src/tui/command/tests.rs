@@ -0,0 +1,90 @@
+use super::*;
+
+fn prompt(text: &str) -> Prompt {
+    let mut prompt = Prompt::default();
+    prompt.line.set(text.to_owned());
+    prompt
+}
+
+fn contacts() -> Vec<String> {
+    ["alice", "Alicia", "bob", "alice"]
+        .iter()
+        .map(|&name| name.to_owned())
+        .collect()
+}
+
+#[test]
+fn commands_parse_with_their_arguments() {
+    assert_eq!(
+        Command::parse("add abc  Dave Smith ").unwrap(),
+        Command::Add {
+            onion: "abc".to_owned(),
+            name: "Dave Smith".to_owned(),
+        }
+    );
+    assert_eq!(
+        Command::parse("o  bob").unwrap(),
+        Command::Open("bob".to_owned())
+    );
+    assert_eq!(
+        Command::parse("export chat.txt").unwrap(),
+        Command::Export(PathBuf::from("chat.txt"))
+    );
+    assert_eq!(Command::parse("q").unwrap(), Command::Quit);
+    assert_eq!(
+        Command::parse("add abc").unwrap_err().to_string(),
+        "usage: :add <onion> <name>"
+    );
+    assert_eq!(
+        Command::parse("search").unwrap_err().to_string(),
+        "usage: :search <text>"
+    );
+    assert_eq!(
+        Command::parse("frob").unwrap_err().to_string(),
+        "unknown command `frob`"
+    );
+    assert!(Command::parse("quit now").is_err());
+}
+
+#[test]
+fn tab_completes_commands_then_cycles_contact_names() {
+    let mut p = prompt("pro");
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "profile ");
+    // as far as the candidates agree, ignoring case
+    let mut p = prompt("open a");
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "open alic");
+    assert_eq!(p.candidates().0, ["alice", "Alicia"]);
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "open alice");
+    assert_eq!(p.candidates().1, Some(0));
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "open Alicia");
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "open alice");
+    // typing starts over
+    p.edit(pancurses::Input::KeyBackspace);
+    assert!(p.candidates().0.is_empty());
+    // only contact names after the commands that take them
+    let mut p = prompt("search a");
+    p.complete(&contacts());
+    assert_eq!(p.line.text(), "search a");
+}
+
+#[test]
+fn history_goes_back_and_returns_to_what_was_typed() {
+    let history = ["open bob".to_owned(), "quit".to_owned()];
+    let mut p = prompt("sea");
+    p.browse(&history, false);
+    assert_eq!(p.line.text(), "sea");
+    p.browse(&history, true);
+    assert_eq!(p.line.text(), "quit");
+    p.browse(&history, true);
+    p.browse(&history, true);
+    assert_eq!(p.line.text(), "open bob");
+    p.browse(&history, false);
+    assert_eq!(p.line.text(), "quit");
+    p.browse(&history, false);
+    assert_eq!(p.line.text(), "sea");
+}
@@ -0,0 +1,213 @@
+use pancurses::Input;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::text;
+
+/// Text being written in the input pane. `cursor` is a byte offset into
+/// `text` and always sits on a grapheme boundary.
+#[derive(Clone, Debug, Default)]
+pub struct Composer {
+    text: String,
+    cursor: usize,
+}
+impl Composer {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    /// Replaces the text, leaving the cursor at the end.
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+    /// Applies the editing keys shared by every text field. Returns whether
+    /// the key was one of them.
+    pub fn edit(&mut self, key: Input) -> bool {
+        match key {
+            Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08') => {
+                self.backspace()
+            }
+            Input::Character('\x17') => self.delete_word(),
+            Input::KeyDC | Input::Character('\x04') => self.delete(),
+            Input::KeyLeft | Input::Character('\x02') => self.left(),
+            Input::KeyRight | Input::Character('\x06') => self.right(),
+            Input::KeyHome | Input::Character('\x01') => self.home(),
+            Input::KeyEnd | Input::Character('\x05') => self.end(),
+            Input::Character(c) if !c.is_control() => self.insert(c),
+            _ => return false,
+        }
+        true
+    }
+    /// Empties the composer, returning what was written.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+    /// Deletes back to the start of the word before the cursor.
+    pub fn delete_word(&mut self) {
+        let before = &self.text[..self.cursor];
+        let trimmed = before.trim_end();
+        let start = trimmed
+            .rfind(char::is_whitespace)
+            .map_or(0, |i| i + trimmed[i..].chars().next().unwrap().len_utf8());
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+    pub fn left(&mut self) {
+        self.cursor = self.prev_boundary();
+    }
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+    pub fn home(&mut self) {
+        self.cursor = self.line_start(self.cursor);
+    }
+    pub fn end(&mut self) {
+        self.cursor = self.line_end(self.cursor);
+    }
+    pub fn up(&mut self) {
+        let start = self.line_start(self.cursor);
+        if start == 0 {
+            self.cursor = 0;
+            return;
+        }
+        let col = text::width(&self.text[start..self.cursor]);
+        self.cursor = self.column_in_line(self.line_start(start - 1), col);
+    }
+    pub fn down(&mut self) {
+        let end = self.line_end(self.cursor);
+        if end == self.text.len() {
+            self.cursor = end;
+            return;
+        }
+        let col = text::width(&self.text[self.line_start(self.cursor)..self.cursor]);
+        self.cursor = self.column_in_line(end + 1, col);
+    }
+
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+    fn line_end(&self, pos: usize) -> usize {
+        self.text[pos..]
+            .find('\n')
+            .map_or(self.text.len(), |i| pos + i)
+    }
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+    /// Offset of the grapheme at display column `col` of the line starting
+    /// at `start`, or the end of that line if it is shorter.
+    fn column_in_line(&self, start: usize, col: usize) -> usize {
+        let end = self.line_end(start);
+        let mut used = 0;
+        for (i, grapheme) in self.text[start..end].grapheme_indices(true) {
+            used += text::grapheme_width(grapheme);
+            if used > col {
+                return start + i;
+            }
+        }
+        end
+    }
+
+    /// Splits the text into rows of at most `width` columns, returning them
+    /// with the row and display column the cursor falls on.
+    pub fn layout(&self, width: usize) -> (Vec<String>, (usize, usize)) {
+        let width = std::cmp::max(width, 2);
+        let mut rows = Vec::new();
+        let mut cursor = (0, 0);
+        let mut offset = 0;
+        for line in self.text.split('\n') {
+            let mut row = String::new();
+            let mut col = 0;
+            for (i, grapheme) in line.grapheme_indices(true) {
+                let grapheme = text::printable(grapheme);
+                let w = text::grapheme_width(&grapheme);
+                if col + w > width {
+                    rows.push(std::mem::take(&mut row));
+                    col = 0;
+                }
+                if offset + i == self.cursor {
+                    cursor = (rows.len(), col);
+                }
+                row.push_str(&grapheme);
+                col += w;
+            }
+            if offset + line.len() == self.cursor {
+                if col >= width {
+                    rows.push(std::mem::take(&mut row));
+                    col = 0;
+                }
+                cursor = (rows.len(), col);
+            }
+            rows.push(row);
+            offset += line.len() + 1;
+        }
+        (rows, cursor)
+    }
+}
+
+/// How far back through earlier entries, such as sent messages, a
+/// composer is, so going forward past the newest brings back what was
+/// typed.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    /// how many entries back from the newest the text was taken from
+    back: Option<usize>,
+    /// the text as typed, from before going back
+    typed: String,
+}
+impl History {
+    pub fn browsing(&self) -> bool {
+        self.back.is_some()
+    }
+    /// Stops browsing, leaving the composer as it is.
+    pub fn reset(&mut self) {
+        self.back = None;
+    }
+    /// Puts an earlier entry from `entries`, oldest first, in the
+    /// composer, or a later one if `older` is not set. Returns whether the
+    /// composer changed.
+    pub fn browse(&mut self, composer: &mut Composer, entries: &[String], older: bool) -> bool {
+        let back = match (self.back, older) {
+            (None, true) if !entries.is_empty() => {
+                self.typed = composer.text().to_owned();
+                0
+            }
+            (Some(back), true) => std::cmp::min(back + 1, entries.len() - 1),
+            (Some(0), false) => {
+                self.back = None;
+                composer.set(std::mem::take(&mut self.typed));
+                return true;
+            }
+            (Some(back), false) => back - 1,
+            (None, _) => return false,
+        };
+        self.back = Some(back);
+        composer.set(entries[entries.len() - 1 - back].clone());
+        true
+    }
+}
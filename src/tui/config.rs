@@ -0,0 +1,89 @@
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use failure::{bail, Error, ResultExt};
+
+/// Where the TUI looks for its configuration: `$CUPS_CONFIG_DIR` if set,
+/// otherwise `cups-cli` under `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Where the TUI keeps what it remembers between runs, such as drafts:
+/// `$CUPS_CONFIG_DIR` if set, otherwise `cups-cli` under `$XDG_STATE_HOME`
+/// or `~/.local/state`.
+pub fn state_dir() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+fn base_dir(var: &str, default: &str) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("CUPS_CONFIG_DIR") {
+        return Some(dir.into());
+    }
+    let base = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(default),
+    };
+    Some(base.join("cups-cli"))
+}
+
+/// Reads the file `name` from the configuration directory, or `None` if
+/// there is no such file.
+pub fn read(name: &str) -> Result<Option<(PathBuf, String)>, Error> {
+    read_from(dir(), name)
+}
+
+/// Reads the file `name` from the state directory, or `None` if there is
+/// no such file.
+pub fn read_state(name: &str) -> Result<Option<(PathBuf, String)>, Error> {
+    read_from(state_dir(), name)
+}
+
+fn read_from(dir: Option<PathBuf>, name: &str) -> Result<Option<(PathBuf, String)>, Error> {
+    let path = match dir {
+        Some(dir) => dir.join(name),
+        None => return Ok(None),
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some((path, text))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+            .with_context(|_| format!("reading {}", path.display()))
+            .map_err(Error::from),
+    }
+}
+
+/// Replaces the file `name` in the state directory, creating the
+/// directory if need be. The text is written alongside first and then
+/// moved into place, so the file is never left half written. What is kept
+/// there, such as unsent messages, is readable only by this user.
+pub fn write_state(name: &str, text: &str) -> Result<(), Error> {
+    let dir = match state_dir() {
+        Some(dir) => dir,
+        None => bail!("no home directory to keep {} in", name),
+    };
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder
+        .create(&dir)
+        .with_context(|_| format!("creating {}", dir.display()))?;
+    let path = dir.join(name);
+    let new = dir.join(format!("{}.new", name));
+    // one left over from before may be readable by others
+    let _ = std::fs::remove_file(&new);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(&new)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .with_context(|_| format!("writing {}", new.display()))?;
+    std::fs::rename(&new, &path).with_context(|_| format!("writing {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use pancurses::Input;
+use unicode_segmentation::UnicodeSegmentation;
+
+use cupslib::MAX_NAME_SIZE;
+
+use super::backend::{Backend, Pane};
+use super::composer::Composer;
+use super::state::Rect;
+use super::text;
+use super::theme::{Role, Theme};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DialogKind {
+    Add,
+    Rename,
+}
+
+#[derive(Clone, Debug)]
+pub enum DialogStatus {
+    Editing,
+    Saving,
+    Failed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Address,
+    Name,
+}
+
+pub enum DialogAction {
+    None,
+    Cancel,
+    /// save `name` for the contact at the onion address
+    Submit(String, String),
+}
+
+/// What is wrong with an onion address as typed so far, if anything.
+pub fn address_error(address: &str) -> Option<String> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Some("required".to_owned());
+    }
+    cupslib::onion_to_pubkey(address)
+        .err()
+        .map(|e| e.find_root_cause().to_string())
+}
+
+pub fn name_error(name: &str) -> Option<String> {
+    if name.trim().is_empty() {
+        Some("required".to_owned())
+    } else if name.len() > MAX_NAME_SIZE {
+        Some(format!("at most {} bytes", MAX_NAME_SIZE))
+    } else {
+        None
+    }
+}
+
+/// Form for adding a contact, or renaming one. Both end in `add_user`,
+/// which overwrites the name of a contact that already exists.
+#[derive(Clone, Debug)]
+pub struct Dialog {
+    pub kind: DialogKind,
+    address: Composer,
+    name: Composer,
+    field: Field,
+    pub status: DialogStatus,
+}
+impl Dialog {
+    pub fn add() -> Self {
+        Dialog {
+            kind: DialogKind::Add,
+            address: Composer::default(),
+            name: Composer::default(),
+            field: Field::Address,
+            status: DialogStatus::Editing,
+        }
+    }
+    /// Renaming keeps the address fixed and starts on the current name.
+    pub fn rename(onion: String, name: &str) -> Self {
+        let mut dialog = Dialog::add();
+        dialog.kind = DialogKind::Rename;
+        dialog.address.set(onion);
+        dialog.name.set(name.to_owned());
+        dialog.field = Field::Name;
+        dialog
+    }
+    pub fn address_error(&self) -> Option<String> {
+        address_error(self.address.text())
+    }
+    pub fn name_error(&self) -> Option<String> {
+        name_error(self.name.text())
+    }
+    pub fn key(&mut self, key: Input) -> DialogAction {
+        if let DialogStatus::Saving = self.status {
+            return DialogAction::None;
+        }
+        match key {
+            Input::Character('\x1b') => return DialogAction::Cancel,
+            Input::Character('\t') | Input::KeySTab | Input::KeyUp | Input::KeyDown => {
+                if self.kind == DialogKind::Add {
+                    self.field = match self.field {
+                        Field::Address => Field::Name,
+                        Field::Name => Field::Address,
+                    };
+                }
+            }
+            Input::Character('\n') | Input::Character('\r') | Input::KeyEnter => {
+                if self.address_error().is_none() && self.name_error().is_none() {
+                    self.status = DialogStatus::Saving;
+                    return DialogAction::Submit(
+                        self.address.text().trim().to_owned(),
+                        self.name.text().to_owned(),
+                    );
+                }
+            }
+            key => {
+                let field = match self.field {
+                    Field::Address => &mut self.address,
+                    Field::Name => &mut self.name,
+                };
+                if field.edit(key) {
+                    self.status = DialogStatus::Editing;
+                }
+            }
+        }
+        DialogAction::None
+    }
+
+    /// Draws the dialog centred over the rest of the interface.
+    pub fn render<B: Backend>(&self, backend: &B, theme: &Theme) {
+        let (rows, cols) = backend.size();
+        let width = std::cmp::min(cols - 4, 72);
+        let height = 10;
+        let win = match backend.pane(Rect {
+            y: (rows - height) / 2,
+            x: (cols - width) / 2,
+            rows: height,
+            cols: width,
+        }) {
+            Some(win) => win,
+            None => return,
+        };
+        let inner = (width - 4) as usize;
+        win.erase();
+        win.attron(theme.style(Role::Border));
+        win.draw_border(theme.borders);
+        win.attroff(theme.style(Role::Border));
+        win.attron(theme.style(Role::Title));
+        win.mvaddstr(
+            0,
+            2,
+            match self.kind {
+                DialogKind::Add => " add contact ",
+                DialogKind::Rename => " rename contact ",
+            },
+        );
+        win.attroff(theme.style(Role::Title));
+        let fields = [
+            (
+                Field::Address,
+                "address",
+                &self.address,
+                self.address_error(),
+            ),
+            (Field::Name, "name", &self.name, self.name_error()),
+        ];
+        for (i, (field, label, text, error)) in fields.iter().enumerate() {
+            let y = 1 + 3 * i as i32;
+            win.mvaddstr(y, 2, label);
+            let active = *field == self.field && self.kind == DialogKind::Add
+                || *field == Field::Name && self.kind == DialogKind::Rename;
+            render_field(&win, theme, (y + 1, 2), inner, text, Role::Field, active);
+            win.mv(y + 2, 2);
+            match error {
+                Some(error) => {
+                    win.attron(theme.style(Role::Error));
+                    win.addstr(text::truncate(error, inner));
+                    win.attroff(theme.style(Role::Error));
+                }
+                None => {
+                    win.attron(theme.style(Role::Muted));
+                    win.addstr("ok");
+                    win.attroff(theme.style(Role::Muted));
+                }
+            }
+        }
+        let status = match &self.status {
+            DialogStatus::Editing => "Enter: save  Tab: next field  Esc: cancel".to_owned(),
+            DialogStatus::Saving => "saving...".to_owned(),
+            DialogStatus::Failed(e) => format!("failed: {}", e),
+        };
+        win.mvaddstr(height - 2, 2, text::truncate(&status, inner));
+        win.noutrefresh();
+    }
+}
+
+/// A one line text field at `(y, x)`, scrolled so the cursor stays
+/// visible, which is only drawn while the field is active.
+pub fn render_field<P: Pane>(
+    win: &P,
+    theme: &Theme,
+    (y, x): (i32, i32),
+    width: usize,
+    field: &Composer,
+    role: Role,
+    active: bool,
+) {
+    let (rows, (_, col)) = field.layout(usize::MAX / 2);
+    let line = rows.concat();
+    // drop whole graphemes off the front until the cursor fits
+    let mut shown = line.as_str();
+    let mut start = 0;
+    while col + 1 > start + width {
+        let first = shown.graphemes(true).next().map_or(shown.len(), str::len);
+        start += text::width(&shown[..first]);
+        shown = &shown[first..];
+    }
+    let shown = text::take_width(shown, width);
+    win.mv(y, x);
+    win.attron(theme.style(role));
+    win.addstr(shown);
+    win.addstr(" ".repeat(width - text::width(shown)));
+    win.attroff(theme.style(role));
+    if active {
+        win.mv(y, x + (col - start) as i32);
+        win.attron(theme.style(Role::Cursor));
+        win.addstr(text::grapheme_at(&line, col).unwrap_or(" "));
+        win.attroff(theme.style(Role::Cursor));
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use failure::{bail, format_err, Error, ResultExt};
+
+use super::config;
+
+/// The file in the state directory drafts are kept in.
+pub const FILE: &str = "drafts";
+
+/// Text written for contacts but not sent yet, by contact.
+pub type Drafts = BTreeMap<[u8; 32], String>;
+
+/// Parses one draft per line: the contact's onion address, a space, and
+/// the text, with backslashes, line breaks and carriage returns escaped.
+pub fn parse(text: &str) -> Result<Drafts, Error> {
+    let mut drafts = Drafts::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let parse_line = || -> Result<([u8; 32], String), Error> {
+            let (onion, draft) = line
+                .split_once(' ')
+                .ok_or_else(|| format_err!("expected an onion address and a draft"))?;
+            Ok((cupslib::onion_to_pubkey(onion)?.0, unescape(draft)?))
+        };
+        let (id, draft) = parse_line().with_context(|_| format!("line {}", i + 1))?;
+        drafts.insert(id, draft);
+    }
+    Ok(drafts)
+}
+
+pub fn format(drafts: &Drafts) -> String {
+    let mut text = String::new();
+    for (id, draft) in drafts {
+        if let Ok(onion) = cupslib::pubkey_to_onion(id) {
+            text.push_str(&format!("{} {}\n", onion, escape(draft)));
+        }
+    }
+    text
+}
+
+fn escape(draft: &str) -> String {
+    draft
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(draft: &str) -> Result<String, Error> {
+    let mut text = String::new();
+    let mut chars = draft.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => text.push('\\'),
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some(c) => bail!("unknown escape `\\{}`", c),
+            None => bail!("`\\` at the end of the line"),
+        }
+    }
+    Ok(text)
+}
+
+/// The drafts left from the last run, if any.
+pub fn load() -> Result<Drafts, Error> {
+    match config::read_state(FILE)? {
+        Some((path, text)) => Ok(parse(&text).with_context(|_| format!("in {}", path.display()))?),
+        None => Ok(Drafts::new()),
+    }
+}
+
+pub fn save(drafts: &Drafts) -> Result<(), Error> {
+    config::write_state(FILE, &format(drafts))
+}
+
+#[cfg(test)]
+mod tests;
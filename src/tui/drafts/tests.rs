@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn drafts_survive_being_written_out() {
+    let mut drafts = Drafts::new();
+    drafts.insert([1; 32], "two\nlines, and a \\ backslash".to_owned());
+    drafts.insert([2; 32], "  spaced  ".to_owned());
+    let text = format(&drafts);
+    assert_eq!(text.lines().count(), 2);
+    assert_eq!(parse(&text).unwrap(), drafts);
+}
+
+#[test]
+fn carriage_returns_are_not_lost_at_line_ends() {
+    // `lines` would take a bare `\r` before the newline as part of it
+    for draft in &["windows\r\nline", "ends in\r", "\r", "\\r"] {
+        let mut drafts = Drafts::new();
+        drafts.insert([1; 32], (*draft).to_owned());
+        let text = format(&drafts);
+        assert!(!text.contains('\r'), "{:?}", text);
+        assert_eq!(parse(&text).unwrap(), drafts);
+    }
+}
+
+#[test]
+fn mistakes_in_the_file_are_reported_by_line() {
+    let onion = cupslib::pubkey_to_onion(&[1; 32]).unwrap();
+    let err = |text: &str| parse(text).unwrap_err().to_string();
+    assert_eq!(err(&format!("{} ok\n\nnotanonion hi", onion)), "line 3");
+    assert_eq!(
+        parse(&format!("{} bad \\t", onion))
+            .unwrap_err()
+            .iter_chain()
+            .last()
+            .unwrap()
+            .to_string(),
+        "unknown escape `\\t`"
+    );
+    assert!(parse(&onion).is_err());
+}
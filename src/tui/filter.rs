@@ -0,0 +1,76 @@
+use cupslib::UserData;
+
+/// Scores how well `pattern` matches `text` as a case-insensitive
+/// subsequence, or `None` if it does not match at all. Runs of consecutive
+/// characters and matches at the start of a word score higher.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let mut next = 0;
+    let mut score = 0;
+    let mut run = 0;
+    let mut prev: Option<char> = None;
+    for c in text.to_lowercase().chars() {
+        if next == pattern.len() {
+            break;
+        }
+        if c == pattern[next] {
+            next += 1;
+            run += 1;
+            score += 1 + 2 * run;
+            if prev.map_or(true, |p| !p.is_alphanumeric()) {
+                score += 5;
+            }
+        } else {
+            run = 0;
+            if next > 0 {
+                score -= 1;
+            }
+        }
+        prev = Some(c);
+    }
+    if next == pattern.len() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Scores `pattern` as part of an onion address. Unlike a name, an address
+/// is only matched where the pattern appears in it whole, as a random one
+/// holds most short patterns as a subsequence. A match at the start scores
+/// as a name's would.
+fn onion_score(pattern: &str, onion: &str) -> Option<i64> {
+    let at = onion.find(&pattern.to_lowercase())?;
+    let len = pattern.chars().count() as i64;
+    // what a run of that many characters scores in `fuzzy_score`
+    let score = len * (len + 2);
+    Some(if at == 0 { score + 5 } else { score })
+}
+
+/// Indices of the contacts in `users` matching `pattern` by name, fuzzily,
+/// or onion address, best match first. An empty pattern keeps every contact in the
+/// order the server sent them.
+pub fn filter_users(users: &[UserData], pattern: &str) -> Vec<usize> {
+    if pattern.is_empty() {
+        return (0..users.len()).collect();
+    }
+    let mut scored: Vec<(i64, usize)> = users
+        .iter()
+        .enumerate()
+        .filter_map(|(i, user)| {
+            let name = user
+                .name
+                .as_ref()
+                .and_then(|name| fuzzy_score(pattern, name.as_str()));
+            let onion = cupslib::pubkey_to_onion(&user.id)
+                .ok()
+                .and_then(|onion| onion_score(pattern, &onion));
+            std::cmp::max(name, onion).map(|score| (score, i))
+        })
+        .collect();
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests;
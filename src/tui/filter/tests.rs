@@ -0,0 +1,25 @@
+use cupslib::Text;
+
+use super::*;
+
+fn user(n: u8, name: Option<&str>) -> UserData {
+    UserData {
+        id: [n; 32],
+        name: name.map(|name| Text::Utf8(name.to_owned())),
+        unreads: 0,
+    }
+}
+
+#[test]
+fn onions_only_match_where_the_pattern_appears_whole() {
+    // addresses aeaqcaib…a37ead, aibaeaqc…ibejsqd and ambqgayd…
+    let users = vec![user(1, None), user(2, Some("bob")), user(3, Some("alice"))];
+    assert!(filter_users(&users, "cab").is_empty());
+    assert_eq!(filter_users(&users, "37EAD"), [0]);
+    // while names match fuzzily
+    assert_eq!(filter_users(&users, "bb"), [1]);
+    assert_eq!(filter_users(&users, "ace"), [2]);
+    // the start of an address ranks ahead of a match further in
+    assert_eq!(filter_users(&users, "aib"), [1, 0]);
+    assert_eq!(filter_users(&users, ""), [0, 1, 2]);
+}
@@ -0,0 +1,563 @@
+use failure::{bail, format_err, Error, ResultExt};
+use pancurses::Input;
+
+use super::config;
+
+/// Name of the keymap file in the configuration directory.
+pub const FILE: &str = "keys";
+
+/// A key as bindings see it: `alt` is set when it followed an escape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub input: Input,
+    pub alt: bool,
+}
+impl Key {
+    /// The key as it came from the terminal, folding together the different
+    /// codes terminals send for enter and backspace.
+    pub fn new(input: Input, alt: bool) -> Self {
+        let input = match input {
+            Input::Character('\r') | Input::KeyEnter => Input::Character('\n'),
+            Input::Character('\x7f') | Input::Character('\x08') => Input::KeyBackspace,
+            input => input,
+        };
+        Key { input, alt }
+    }
+    /// Parses a key as written in the keymap file: a single character, a
+    /// name like `Enter` or `PgUp`, `C-x` for control and `M-x` for alt.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        if let Some(rest) = s.strip_prefix("M-") {
+            let key = Key::parse(rest)?;
+            return Ok(Key { alt: true, ..key });
+        }
+        if let Some(&(_, input)) = NAMED.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(Key::new(input, false));
+        }
+        let mut chars = s.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _ => match s.strip_prefix("C-").map(str::chars) {
+                Some(mut chars) => match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphabetic() || "@[\\]^_".contains(c) => {
+                        (c.to_ascii_uppercase() as u8 & 0x1f) as char
+                    }
+                    _ => bail!("unknown key `{}`", s),
+                },
+                None => bail!("unknown key `{}`", s),
+            },
+        };
+        Ok(Key::new(Input::Character(c), false))
+    }
+    /// Whether the key would be typed as text where text can be typed,
+    /// with or without alt.
+    pub fn is_text(&self) -> bool {
+        matches!(self.input, Input::Character(c) if !c.is_control())
+    }
+}
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.alt {
+            write!(f, "M-")?;
+        }
+        if let Some((name, _)) = NAMED.iter().find(|(_, input)| *input == self.input) {
+            return write!(f, "{}", name);
+        }
+        match self.input {
+            Input::Character(c) if (c as u32) < 0x20 => {
+                write!(f, "C-{}", ((c as u8 | 0x40) as char).to_ascii_lowercase())
+            }
+            Input::Character(c) => write!(f, "{}", c),
+            input => write!(f, "{:?}", input),
+        }
+    }
+}
+
+const NAMED: &[(&str, Input)] = &[
+    ("Tab", Input::Character('\t')),
+    ("S-Tab", Input::KeySTab),
+    ("Enter", Input::Character('\n')),
+    ("Esc", Input::Character('\x1b')),
+    ("Space", Input::Character(' ')),
+    ("Backspace", Input::KeyBackspace),
+    ("Delete", Input::KeyDC),
+    ("Insert", Input::KeyIC),
+    ("Up", Input::KeyUp),
+    ("Down", Input::KeyDown),
+    ("Left", Input::KeyLeft),
+    ("Right", Input::KeyRight),
+    ("Home", Input::KeyHome),
+    ("End", Input::KeyEnd),
+    ("PgUp", Input::KeyPPage),
+    ("PgDn", Input::KeyNPage),
+    ("F1", Input::KeyF1),
+    ("F2", Input::KeyF2),
+    ("F3", Input::KeyF3),
+    ("F4", Input::KeyF4),
+    ("F5", Input::KeyF5),
+    ("F6", Input::KeyF6),
+    ("F7", Input::KeyF7),
+    ("F8", Input::KeyF8),
+    ("F9", Input::KeyF9),
+    ("F10", Input::KeyF10),
+    ("F11", Input::KeyF11),
+    ("F12", Input::KeyF12),
+];
+
+/// Where a binding applies. Keys are looked up in the context of whatever
+/// has focus, then in `Global`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+    Global,
+    Sidebar,
+    Feed,
+    Namebar,
+    /// the composer, while typing
+    Insert,
+    /// the composer, while keys are commands (vim's normal mode)
+    Normal,
+}
+impl Context {
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Sidebar => "sidebar",
+            Context::Feed => "feed",
+            Context::Namebar => "namebar",
+            Context::Insert => "insert",
+            Context::Normal => "normal",
+        }
+    }
+    /// The actions that can be bound in this context.
+    fn actions(self) -> &'static [Action] {
+        use Action::*;
+        match self {
+            Context::Global => &[Quit, NextPane, PrevPane, Help, NextUnread, Command],
+            Context::Sidebar => &[
+                Up,
+                Down,
+                PageUp,
+                PageDown,
+                Top,
+                Bottom,
+                Open,
+                Filter,
+                ClearFilter,
+                AddContact,
+                RenameContact,
+            ],
+            Context::Feed => &[
+                Up,
+                Down,
+                PageUp,
+                PageDown,
+                Top,
+                Bottom,
+                Search,
+                NextMatch,
+                PrevMatch,
+                ClearSearch,
+            ],
+            Context::Namebar => &[AddContact, RenameContact],
+            Context::Insert => &[Send, Newline, Up, Down, NormalMode, Edit],
+            Context::Normal => &[
+                Send,
+                InsertMode,
+                Append,
+                InsertAtStart,
+                AppendAtEnd,
+                OpenLine,
+                Left,
+                Right,
+                Up,
+                Down,
+                LineStart,
+                LineEnd,
+                DeleteChar,
+                Edit,
+            ],
+        }
+    }
+}
+
+const CONTEXTS: [Context; 6] = [
+    Context::Global,
+    Context::Sidebar,
+    Context::Feed,
+    Context::Namebar,
+    Context::Insert,
+    Context::Normal,
+];
+
+/// What a key can be bound to. What moving up or down means depends on
+/// the context: the sidebar highlight, the feed or the composer cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    NextPane,
+    PrevPane,
+    Help,
+    NextUnread,
+    Command,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Open,
+    Filter,
+    ClearFilter,
+    AddContact,
+    RenameContact,
+    Search,
+    NextMatch,
+    PrevMatch,
+    ClearSearch,
+    Send,
+    Newline,
+    NormalMode,
+    InsertMode,
+    Append,
+    InsertAtStart,
+    AppendAtEnd,
+    OpenLine,
+    Left,
+    Right,
+    LineStart,
+    LineEnd,
+    DeleteChar,
+    Edit,
+}
+impl Action {
+    /// The name used in the keymap file, and a description for the help.
+    fn names(self) -> (&'static str, &'static str) {
+        use Action::*;
+        match self {
+            Quit => ("quit", "quit"),
+            NextPane => ("next-pane", "next pane"),
+            PrevPane => ("prev-pane", "previous pane"),
+            Help => ("help", "show this help"),
+            NextUnread => ("next-unread", "next unread conversation"),
+            Command => ("command", "run a command"),
+            Up => ("up", "up"),
+            Down => ("down", "down"),
+            PageUp => ("page-up", "page up"),
+            PageDown => ("page-down", "page down"),
+            Top => ("top", "to the top"),
+            Bottom => ("bottom", "to the bottom"),
+            Open => ("open", "open conversation"),
+            Filter => ("filter", "filter contacts"),
+            ClearFilter => ("clear-filter", "clear the filter"),
+            AddContact => ("add-contact", "add contact"),
+            RenameContact => ("rename-contact", "rename contact"),
+            Search => ("search", "search the messages"),
+            NextMatch => ("next-match", "older match"),
+            PrevMatch => ("prev-match", "newer match"),
+            ClearSearch => ("clear-search", "clear the search"),
+            Send => ("send", "send"),
+            Newline => ("newline", "new line"),
+            NormalMode => ("normal-mode", "normal mode"),
+            InsertMode => ("insert", "insert before the cursor"),
+            Append => ("append", "insert after the cursor"),
+            InsertAtStart => ("insert-at-start", "insert at the line start"),
+            AppendAtEnd => ("append-at-end", "insert at the line end"),
+            OpenLine => ("open-line", "insert on a new line"),
+            Left => ("left", "left"),
+            Right => ("right", "right"),
+            LineStart => ("line-start", "to the line start"),
+            LineEnd => ("line-end", "to the line end"),
+            DeleteChar => ("delete-char", "delete character"),
+            Edit => ("edit", "write in $EDITOR"),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        self.names().0
+    }
+    pub fn description(self) -> &'static str {
+        self.names().1
+    }
+}
+
+/// A heading and the bindings listed under it, for the help overlay.
+pub type HelpSection = (String, Vec<(String, String)>);
+
+/// Which keys trigger which actions.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    /// every action that can be bound, in the order the help lists them
+    bindings: Vec<(Context, Action, Vec<Key>)>,
+}
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::emacs()
+    }
+}
+impl Keymap {
+    fn preset(keys: &[(Context, Action, &str)]) -> Self {
+        let mut keymap = Keymap {
+            bindings: CONTEXTS
+                .iter()
+                .flat_map(|&c| c.actions().iter().map(move |&a| (c, a, Vec::new())))
+                .collect(),
+        };
+        for &(context, action, keys) in keys {
+            let keys = keys
+                .split_whitespace()
+                .map(|k| Key::parse(k).unwrap())
+                .collect();
+            keymap.bind(context, action, keys);
+        }
+        keymap
+    }
+    /// Arrow keys plus emacs style control and alt keys. The composer is
+    /// always typing.
+    pub fn emacs() -> Self {
+        use Action::*;
+        use Context::*;
+        Keymap::preset(&[
+            (Global, Quit, "q"),
+            (Global, NextPane, "Tab"),
+            (Global, PrevPane, "S-Tab"),
+            (Global, Help, "? F1"),
+            (Global, NextUnread, "M-a"),
+            (Global, Command, ": M-x"),
+            (Sidebar, Up, "Up k C-p"),
+            (Sidebar, Down, "Down j C-n"),
+            (Sidebar, PageUp, "PgUp M-v"),
+            (Sidebar, PageDown, "PgDn C-v"),
+            (Sidebar, Top, "Home M-<"),
+            (Sidebar, Bottom, "End M->"),
+            (Sidebar, Open, "Enter"),
+            (Sidebar, Filter, "/ C-s"),
+            (Sidebar, ClearFilter, "Esc C-g"),
+            (Sidebar, AddContact, "a"),
+            (Sidebar, RenameContact, "r"),
+            (Feed, Up, "Up k C-p"),
+            (Feed, Down, "Down j C-n"),
+            (Feed, PageUp, "PgUp M-v"),
+            (Feed, PageDown, "PgDn C-v Space"),
+            (Feed, Top, "Home M-<"),
+            (Feed, Bottom, "End M->"),
+            (Feed, Search, "/ C-f C-s"),
+            (Feed, NextMatch, "n"),
+            (Feed, PrevMatch, "N"),
+            (Feed, ClearSearch, "Esc C-g"),
+            (Namebar, AddContact, "a"),
+            (Namebar, RenameContact, "r"),
+            (Insert, Send, "Enter"),
+            (Insert, Newline, "M-Enter"),
+            (Insert, Up, "Up C-p"),
+            (Insert, Down, "Down C-n"),
+            (Insert, Edit, "M-e"),
+        ])
+    }
+    /// Vim style movement, with a modal composer: Esc leaves insert mode,
+    /// and i, a, o and friends go back to it.
+    pub fn vim() -> Self {
+        use Action::*;
+        use Context::*;
+        Keymap::preset(&[
+            (Global, Quit, "q"),
+            (Global, NextPane, "Tab"),
+            (Global, PrevPane, "S-Tab"),
+            (Global, Help, "? F1"),
+            (Global, NextUnread, "M-a"),
+            (Global, Command, ":"),
+            (Sidebar, Up, "k Up"),
+            (Sidebar, Down, "j Down"),
+            (Sidebar, PageUp, "C-b C-u PgUp"),
+            (Sidebar, PageDown, "C-f C-d PgDn"),
+            (Sidebar, Top, "g Home"),
+            (Sidebar, Bottom, "G End"),
+            (Sidebar, Open, "Enter l"),
+            (Sidebar, Filter, "/"),
+            (Sidebar, ClearFilter, "Esc"),
+            (Sidebar, AddContact, "a"),
+            (Sidebar, RenameContact, "r"),
+            (Feed, Up, "k Up"),
+            (Feed, Down, "j Down"),
+            (Feed, PageUp, "C-b C-u PgUp"),
+            (Feed, PageDown, "C-f C-d PgDn"),
+            (Feed, Top, "g Home"),
+            (Feed, Bottom, "G End"),
+            // C-f pages down, as in vim
+            (Feed, Search, "/ C-s"),
+            (Feed, NextMatch, "n"),
+            (Feed, PrevMatch, "N"),
+            (Feed, ClearSearch, "Esc"),
+            (Namebar, AddContact, "a"),
+            (Namebar, RenameContact, "r"),
+            (Insert, Send, "Enter"),
+            (Insert, Up, "Up"),
+            (Insert, Down, "Down"),
+            (Insert, NormalMode, "Esc"),
+            (Insert, Edit, "M-e"),
+            (Normal, Send, "Enter"),
+            (Normal, InsertMode, "i Insert"),
+            (Normal, Append, "a"),
+            (Normal, InsertAtStart, "I"),
+            (Normal, AppendAtEnd, "A"),
+            (Normal, OpenLine, "o"),
+            (Normal, Left, "h Left"),
+            (Normal, Right, "l Right"),
+            (Normal, Up, "k Up"),
+            (Normal, Down, "j Down"),
+            (Normal, LineStart, "0 Home"),
+            (Normal, LineEnd, "$ End"),
+            (Normal, DeleteChar, "x Delete"),
+            (Normal, Edit, "v"),
+        ])
+    }
+    /// Replaces the keys bound to `action` in `context`.
+    fn bind(&mut self, context: Context, action: Action, keys: Vec<Key>) {
+        if let Some(binding) = self
+            .bindings
+            .iter_mut()
+            .find(|(c, a, _)| *c == context && *a == action)
+        {
+            binding.2 = keys;
+        }
+    }
+    /// The action bound to `key` in `context`. A key pressed with alt falls
+    /// back to whatever the key does on its own.
+    pub fn action(&self, context: Context, key: Key) -> Option<Action> {
+        self.bound(context, key).or_else(|| {
+            if key.alt {
+                self.bound(context, Key { alt: false, ..key })
+            } else {
+                None
+            }
+        })
+    }
+    /// The action bound to exactly `key` in `context`.
+    pub fn bound(&self, context: Context, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(c, _, keys)| *c == context && keys.contains(&key))
+            .map(|&(_, action, _)| action)
+    }
+    pub fn keys(&self, context: Context, action: Action) -> &[Key] {
+        self.bindings
+            .iter()
+            .find(|(c, a, _)| *c == context && *a == action)
+            .map_or(&[], |(_, _, keys)| keys)
+    }
+    /// Whether the composer has a normal mode to go to.
+    pub fn modal(&self) -> bool {
+        !self.keys(Context::Insert, Action::NormalMode).is_empty()
+    }
+    /// A short list of the first key bound to each of `actions`, like
+    /// "a add  r rename".
+    pub fn hint(&self, context: Context, actions: &[(Action, &str)]) -> String {
+        let hints: Vec<String> = actions
+            .iter()
+            .filter_map(|&(action, label)| {
+                let key = self.keys(context, action).first()?;
+                Some(format!("{} {}", key, label))
+            })
+            .collect();
+        hints.join("  ")
+    }
+    /// The bindings in each of `contexts`, leaving out unbound actions.
+    pub fn help(&self, contexts: &[Context]) -> Vec<HelpSection> {
+        contexts
+            .iter()
+            .map(|&context| {
+                let lines = self
+                    .bindings
+                    .iter()
+                    .filter(|(c, _, keys)| *c == context && !keys.is_empty())
+                    .map(|(_, action, keys)| {
+                        let keys: Vec<String> = keys.iter().map(Key::to_string).collect();
+                        (keys.join(" "), action.description().to_owned())
+                    })
+                    .collect();
+                (context.name().to_owned(), lines)
+            })
+            .collect()
+    }
+    /// Reads a keymap file. It may start by picking a preset to build on,
+    /// then rebinds actions section by section:
+    ///
+    /// ```text
+    /// preset = vim
+    ///
+    /// [global]
+    /// quit = q F10
+    /// [feed]
+    /// top =
+    /// ```
+    ///
+    /// An action given no keys is unbound.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut keymap = Keymap::emacs();
+        let mut context = None;
+        let mut bound = false;
+        for (i, line) in text.lines().enumerate() {
+            let mut parse_line = |line: &str| -> Result<(), Error> {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    return Ok(());
+                }
+                if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                    let name = name.trim();
+                    context = Some(
+                        *CONTEXTS
+                            .iter()
+                            .find(|c| c.name() == name)
+                            .ok_or_else(|| format_err!("unknown section `[{}]`", name))?,
+                    );
+                    return Ok(());
+                }
+                let (name, keys) = match line.find('=') {
+                    Some(i) => (line[..i].trim(), &line[i + 1..]),
+                    None => bail!("expected `action = keys`"),
+                };
+                let context = match context {
+                    Some(context) => context,
+                    None if name == "preset" => {
+                        if bound {
+                            bail!("the preset must come before any bindings");
+                        }
+                        keymap = match keys.trim() {
+                            "emacs" => Keymap::emacs(),
+                            "vim" => Keymap::vim(),
+                            preset => bail!("unknown preset `{}`", preset),
+                        };
+                        return Ok(());
+                    }
+                    None => bail!("bindings must be in a section, like `[global]`"),
+                };
+                let action = *context
+                    .actions()
+                    .iter()
+                    .find(|a| a.name() == name)
+                    .ok_or_else(|| {
+                        format_err!("unknown action `{}` in [{}]", name, context.name())
+                    })?;
+                let keys = keys
+                    .split_whitespace()
+                    .map(Key::parse)
+                    .collect::<Result<_, _>>()?;
+                keymap.bind(context, action, keys);
+                bound = true;
+                Ok(())
+            };
+            parse_line(line).with_context(|_| format!("line {}", i + 1))?;
+        }
+        Ok(keymap)
+    }
+    /// The keymap from the configuration directory, or the default if there
+    /// is none.
+    pub fn load() -> Result<Self, Error> {
+        match config::read(FILE)? {
+            Some((path, text)) => {
+                Ok(Keymap::parse(&text).with_context(|_| format!("in {}", path.display()))?)
+            }
+            None => Ok(Keymap::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,123 @@
+use super::*;
+
+fn key(s: &str) -> Key {
+    Key::parse(s).unwrap()
+}
+
+#[test]
+fn keys_parse_and_print_the_same() {
+    for s in &[
+        "q", "?", "Enter", "S-Tab", "C-v", "M-<", "M-Enter", "PgDn", "F1", "Space",
+    ] {
+        assert_eq!(key(s).to_string(), *s);
+    }
+    assert_eq!(key("C-v").input, Input::Character('\x16'));
+    assert_eq!(key("C-h").input, Input::KeyBackspace);
+    assert_eq!(key("enter"), key("Enter"));
+    assert!(Key::parse("C-").is_err());
+    assert!(Key::parse("Hyper-x").is_err());
+}
+
+#[test]
+fn terminal_variants_of_enter_and_backspace_match() {
+    let keymap = Keymap::emacs();
+    for &input in &[
+        Input::Character('\n'),
+        Input::Character('\r'),
+        Input::KeyEnter,
+    ] {
+        let action = keymap.action(Context::Insert, Key::new(input, false));
+        assert_eq!(action, Some(Action::Send));
+    }
+    assert_eq!(Key::new(Input::Character('\x7f'), false), key("Backspace"));
+}
+
+#[test]
+fn alt_falls_back_to_the_plain_key() {
+    let keymap = Keymap::emacs();
+    let enter = Key::new(Input::Character('\n'), true);
+    assert_eq!(keymap.action(Context::Insert, enter), Some(Action::Newline));
+    let down = Key::new(Input::Character('j'), true);
+    assert_eq!(keymap.action(Context::Feed, down), Some(Action::Down));
+}
+
+#[test]
+fn files_rebind_on_top_of_a_preset() {
+    let keymap = Keymap::parse(
+        "# mine\n\
+         preset = vim\n\
+         \n\
+         [global]\n\
+         quit = F10 C-x\n\
+         [feed]\n\
+         top =\n",
+    )
+    .unwrap();
+    assert!(keymap.modal());
+    assert_eq!(
+        keymap.keys(Context::Global, Action::Quit),
+        &[key("F10"), key("C-x")]
+    );
+    assert!(keymap.keys(Context::Feed, Action::Top).is_empty());
+    // everything else comes from the preset
+    assert_eq!(
+        keymap.keys(Context::Feed, Action::Bottom),
+        &[key("G"), key("End")]
+    );
+    assert!(!Keymap::default().modal());
+}
+
+#[test]
+fn mistakes_in_files_are_reported_by_line() {
+    let err = |text| Keymap::parse(text).unwrap_err().to_string();
+    assert_eq!(err("[sidebar]\nsend = Enter"), "line 2");
+    let e = Keymap::parse("[sidebar]\nsend = Enter").unwrap_err();
+    assert_eq!(
+        e.iter_chain().last().unwrap().to_string(),
+        "unknown action `send` in [sidebar]"
+    );
+    assert!(Keymap::parse("quit = q").is_err());
+    assert!(Keymap::parse("[global]\nquit = q\npreset = vim").is_err());
+    assert!(Keymap::parse("[insert]\nsend = Hyper-x").is_err());
+    assert!(Keymap::parse("[composer]").is_err());
+}
+
+#[test]
+fn help_lists_bound_actions() {
+    let help = Keymap::emacs().help(&[Context::Namebar, Context::Normal]);
+    assert_eq!(help.len(), 2);
+    assert_eq!(help[0].0, "namebar");
+    assert_eq!(
+        help[0].1,
+        vec![
+            ("a".to_owned(), "add contact".to_owned()),
+            ("r".to_owned(), "rename contact".to_owned())
+        ]
+    );
+    assert!(help[1].1.is_empty());
+}
+
+#[test]
+fn presets_bind_each_key_once_per_context() {
+    for keymap in &[Keymap::emacs(), Keymap::vim()] {
+        for context in &[Context::Sidebar, Context::Feed] {
+            let mut seen: Vec<(Key, Action)> = Vec::new();
+            for &action in context.actions() {
+                for &k in keymap.keys(*context, action) {
+                    if let Some((_, other)) = seen.iter().find(|(s, _)| *s == k) {
+                        panic!("{} is bound to both {:?} and {:?}", k, other, action);
+                    }
+                    seen.push((k, action));
+                }
+            }
+        }
+        assert_eq!(
+            keymap.action(Context::Feed, key("C-s")),
+            Some(Action::Search)
+        );
+    }
+    assert_eq!(
+        Keymap::vim().action(Context::Feed, key("C-f")),
+        Some(Action::PageDown)
+    );
+}
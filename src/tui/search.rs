@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+/// Part of a match on one line of the feed, as a byte range of the line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mark {
+    pub range: Range<usize>,
+    /// set on the match last jumped to
+    pub current: bool,
+}
+
+/// Where `query` occurs in `text`, ignoring case, as byte ranges that do
+/// not overlap. Any whitespace in the query matches any in the text, one
+/// character for a run, so a query can span the lines of a message.
+pub fn find(text: &str, query: &str) -> Vec<Range<usize>> {
+    let query: Vec<char> = query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let mut found = Vec::new();
+    if query.is_empty() {
+        return found;
+    }
+    let mut start = 0;
+    while let Some(c) = text[start..].chars().next() {
+        match match_at(&text[start..], &query) {
+            Some(len) => {
+                found.push(start..start + len);
+                start += len;
+            }
+            None => start += c.len_utf8(),
+        }
+    }
+    found
+}
+
+/// The length of the match of `query` at the start of `text`, if any.
+fn match_at(text: &str, query: &[char]) -> Option<usize> {
+    let mut chars = text.chars();
+    let mut len = 0;
+    for &q in query {
+        let c = chars.next()?;
+        let same = if q.is_whitespace() {
+            c.is_whitespace()
+        } else {
+            c.to_lowercase().eq(q.to_lowercase())
+        };
+        if !same {
+            return None;
+        }
+        len += c.len_utf8();
+    }
+    Some(len)
+}
+
+/// Splits `found`, ranges of `text`, among `lines`, the pieces `text` was
+/// wrapped into (see `text::normalize`). Each line gets the parts of the
+/// matches on it, marking those of the match numbered `current`.
+pub fn marks(
+    text: &str,
+    lines: &[String],
+    found: &[Range<usize>],
+    current: Option<usize>,
+) -> Vec<Vec<Mark>> {
+    let mut pos = 0;
+    let mut marks = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let between = text
+            .get(pos..)
+            .map_or(false, |rest| rest.starts_with([' ', '\n']));
+        if i > 0 && between {
+            pos += 1;
+        }
+        let (start, end) = (pos, pos + line.len());
+        pos = end;
+        let on_line = found
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.start < end && m.end > start)
+            .map(|(j, m)| Mark {
+                range: std::cmp::max(m.start, start) - start..std::cmp::min(m.end, end) - start,
+                current: Some(j) == current,
+            })
+            .collect();
+        marks.push(on_line);
+    }
+    marks
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use super::*;
+use crate::tui::text;
+
+#[test]
+fn matches_ignore_case_and_whitespace() {
+    let text = "Lorem ipsum lorem\nIPSUM ßtraße";
+    assert_eq!(find(text, "lorem"), [0..5, 12..17]);
+    assert_eq!(find(text, "lorem  ipsum"), [0..11, 12..23]);
+    assert_eq!(find(text, "TRAßE").first(), Some(&(26..32)));
+    assert!(find(text, "  ").is_empty());
+    assert_eq!(find("aaaa", "aa"), [0..2, 2..4]);
+}
+
+#[test]
+fn matches_are_split_where_lines_wrap() {
+    let content = "one two  three\n\nabcdefgh";
+    let text = text::normalize(content);
+    let lines = text::wrap(content, 5);
+    assert_eq!(lines, ["one", "two", "three", "", "abcde", "fgh"]);
+    let found = find(&text, "two thr");
+    let found = [found, find(&text, "defg")].concat();
+    let marks: Vec<Vec<(Range<usize>, bool)>> = marks(&text, &lines, &found, Some(1))
+        .into_iter()
+        .map(|line| line.into_iter().map(|m| (m.range, m.current)).collect())
+        .collect();
+    assert_eq!(
+        marks,
+        [
+            vec![],
+            vec![(0..3, false)],
+            vec![(0..3, false)],
+            vec![],
+            vec![(3..5, true)],
+            vec![(0..2, true)],
+        ]
+    );
+}
@@ -0,0 +1,1734 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use failure::Error;
+use pancurses::{
+    mmask_t, Input, BUTTON1_CLICKED, BUTTON1_PRESSED, BUTTON4_PRESSED, BUTTON5_PRESSED,
+};
+
+use cupslib::{Decoded, Message, TimeFormat, UserData};
+
+use super::command::{Command, Prompt};
+use super::composer::{Composer, History};
+use super::dialog::{self, Dialog, DialogAction, DialogStatus};
+use super::drafts::Drafts;
+use super::filter;
+use super::keymap::{Action, Context, Key, Keymap};
+use super::search::{self, Mark};
+use super::text;
+use super::theme::Role;
+use super::ui::Render;
+
+/// How many messages of a conversation are fetched at first, and how many
+/// more each time the feed is scrolled past the oldest one loaded.
+pub const PAGE_SIZE: usize = 50;
+
+/// Smallest terminal, in rows and columns, the panes are laid out in.
+pub const MIN_SIZE: (i32, i32) = (12, 48);
+
+/// Failed fetches in a row after which the status bar reports offline.
+const OFFLINE_AFTER: u32 = 3;
+
+/// Seconds to wait before retrying after the first failed fetch. The wait
+/// doubles with each failure after it, up to `MAX_BACKOFF`.
+const BACKOFF: i64 = 5;
+const MAX_BACKOFF: i64 = 60;
+
+/// Seconds an error or notice stays in the status bar.
+const TOAST_DURATION: i64 = 8;
+
+/// Milliseconds within which a key after an escape that did something of
+/// its own still counts as alt, about as long as a terminal takes to send
+/// the two halves of an alt key.
+const ESCAPE_DELAY: i64 = 50;
+
+/// Where a pane sits on the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub y: i32,
+    pub x: i32,
+    pub rows: i32,
+    pub cols: i32,
+}
+impl Rect {
+    pub fn contains(&self, y: i32, x: i32) -> bool {
+        y >= self.y && y < self.y + self.rows && x >= self.x && x < self.x + self.cols
+    }
+}
+
+/// The panes, laid out for a terminal size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub sidebar: Rect,
+    pub topbar: Rect,
+    pub feed: Rect,
+    pub input: Rect,
+    pub status: Rect,
+}
+impl Layout {
+    /// Splits the screen into panes, or `None` if it is smaller than
+    /// `MIN_SIZE`.
+    pub fn new(rows: i32, cols: i32) -> Option<Self> {
+        if rows < MIN_SIZE.0 || cols < MIN_SIZE.1 {
+            return None;
+        }
+        let status = 1;
+        let rows = rows - status;
+        let side = cols / 4;
+        let top = std::cmp::max(rows / 8, 3);
+        let input = std::cmp::max(rows / 4, 3);
+        let feed = rows - top - input;
+        let rect = |y, x, rows, cols| Rect { y, x, rows, cols };
+        Some(Layout {
+            sidebar: rect(0, 0, rows, side),
+            topbar: rect(0, side, top, cols - side),
+            feed: rect(top, side, feed, cols - side),
+            input: rect(top + feed, side, input, cols - side),
+            status: rect(rows, 0, status, cols),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selected {
+    Sidebar,
+    Input,
+    Feed,
+    Namebar,
+}
+impl Selected {
+    pub fn rotate(&mut self) {
+        match *self {
+            Selected::Sidebar => {
+                *self = Selected::Input;
+            }
+            Selected::Input => {
+                *self = Selected::Feed;
+            }
+            Selected::Feed => {
+                *self = Selected::Namebar;
+            }
+            Selected::Namebar => {
+                *self = Selected::Sidebar;
+            }
+        }
+    }
+    pub fn rotate_back(&mut self) {
+        for _ in 0..3 {
+            self.rotate();
+        }
+    }
+}
+
+/// How fetches from the server have been going, for the status bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connection {
+    /// nothing has come back yet
+    Connecting,
+    Connected,
+    /// the last fetch failed, and another will be tried shortly
+    Retrying,
+    /// fetches keep failing, and are retried less and less often
+    Offline,
+}
+
+/// Everything the state reacts to: input from the terminal, the passing of
+/// time and the results of network requests.
+pub enum Event {
+    Key(Input),
+    Mouse(MouseEvent),
+    /// the terminal now has this many rows and columns
+    Resize(i32, i32),
+    /// time to refresh the contact list and the open conversation
+    Tick,
+    Users(Result<Decoded<UserData>, Error>),
+    Messages([u8; 32], u64, Result<Decoded<Message>, Error>),
+    Sent(u64, Result<(), Error>),
+    Saved(Result<(), Error>),
+    /// where a conversation was written, and how many messages it had
+    Exported(Result<(PathBuf, usize), Error>),
+    /// the draft, as the user's editor left it
+    Edited(Result<String, Error>),
+    DraftsSaved(Result<(), Error>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MouseEvent {
+    pub y: i32,
+    pub x: i32,
+    pub bstate: mmask_t,
+}
+
+/// What the state asks of the outside world in response to an event.
+pub enum Effect {
+    Render(Render),
+    FetchUsers,
+    FetchMessages {
+        id: [u8; 32],
+        /// answered with `Event::Messages` carrying the same number
+        fetch: u64,
+        limit: usize,
+    },
+    Send {
+        /// answered with `Event::Sent` carrying the same number
+        seq: u64,
+        id: [u8; 32],
+        content: String,
+    },
+    /// adds the contact, or renames it if it already exists
+    Save {
+        onion: String,
+        name: String,
+    },
+    /// fetches the whole conversation with `id` and writes it to `path`,
+    /// calling the contact `name`
+    Export {
+        id: [u8; 32],
+        name: String,
+        path: PathBuf,
+        time_format: TimeFormat,
+    },
+    /// keeps the drafts for the next run, before anything that follows
+    SaveDrafts(Drafts),
+    Quit,
+}
+
+#[derive(Clone, Debug)]
+pub enum SendStatus {
+    Pending,
+    Failed(String),
+    /// Accepted by the server. Kept in the feed until a fetch started after
+    /// the given one brings the message back.
+    Sent {
+        after: u64,
+    },
+}
+
+/// A message written in the composer that is not yet part of the fetched
+/// conversation.
+#[derive(Clone, Debug)]
+pub struct Outgoing {
+    seq: u64,
+    id: [u8; 32],
+    content: String,
+    status: SendStatus,
+}
+
+/// What is shown in a box over the panes, until any key is pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlay {
+    /// the bindings that apply to whatever has focus
+    Help,
+    /// a contact's details
+    Profile([u8; 32]),
+}
+
+/// A search of the messages loaded in the feed, whose matches are
+/// highlighted until it is cleared or another conversation is opened.
+#[derive(Clone, Debug)]
+struct FeedSearch {
+    query: String,
+    /// the match last moved to: its message, counted back from the newest,
+    /// and which of that message's matches it is
+    current: Option<(usize, usize)>,
+    /// set while older messages are fetched to carry on looking in
+    looking: bool,
+    /// set when the current match has changed, so the feed scrolls to it
+    reveal: bool,
+}
+
+/// Ways of being told about messages for conversations other than the
+/// open one, besides the sidebar.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Notify {
+    /// ring the terminal bell
+    pub bell: bool,
+    /// show the unread total in the terminal title
+    pub title: bool,
+}
+
+pub struct State {
+    /// effects asked for by the event being handled
+    effects: Vec<Effect>,
+    /// the panes, or `None` until the terminal size is known and while the
+    /// terminal is too small
+    layout: Option<Layout>,
+    pub focus: Selected,
+    /// set when the last key was an escape, which makes the next one alt
+    alt: bool,
+    /// when the next key stops being alt, if the escape did something
+    alt_until: Option<DateTime<Utc>>,
+    pub user_data: Vec<UserData>,
+    users_pending: bool,
+    /// set once contacts have been fetched, so messages that were unread
+    /// before the TUI started are not taken as new
+    users_fetched: bool,
+    /// contacts messages arrived for, most recent first, which the sidebar
+    /// lists ahead of the rest
+    bumped: Vec<[u8; 32]>,
+    pub notify: Notify,
+    /// the unread total last put in the terminal title
+    title_unreads: Option<u64>,
+    pub messages: Vec<Message>,
+    messages_pending: bool,
+    /// counts message fetches, so results can be ordered against sends
+    fetches: u64,
+    /// the conversation shown in the feed
+    open: Option<[u8; 32]>,
+    /// whose conversation `messages` holds
+    loaded: Option<[u8; 32]>,
+    /// how many of the newest messages to fetch for the open conversation
+    limit: usize,
+    /// set once a fetch returns fewer messages than asked for
+    complete: bool,
+    /// feed lines scrolled back from the newest message
+    scroll: usize,
+    search: Option<FeedSearch>,
+    /// searches of the feed, oldest first
+    pub searches: Vec<String>,
+    /// what the sidebar is narrowed down by, if anything
+    pub filter: String,
+    /// set while keys go to the filter rather than the contact list
+    pub filtering: bool,
+    /// indices into `user_data` the sidebar lists, in order
+    visible: Vec<usize>,
+    /// highlighted entry in `visible`
+    pub selected: usize,
+    /// first entry of `visible` shown in the sidebar
+    sidebar_top: usize,
+    /// what is being written to the open conversation
+    pub composer: Composer,
+    /// what was written to other conversations and not sent
+    pub drafts: Drafts,
+    /// every draft as last saved, so they are only saved when they change
+    saved_drafts: Drafts,
+    /// messages sent, oldest first, for Up to bring back
+    pub sent: Vec<String>,
+    recall: History,
+    /// the add or rename form, while one is open
+    pub dialog: Option<Dialog>,
+    overlay: Option<Overlay>,
+    /// the command prompt, while it is open
+    pub prompt: Option<Prompt>,
+    /// commands run from the prompt, oldest first
+    pub commands: Vec<String>,
+    pub keymap: Keymap,
+    /// set while the composer is in normal mode, with a modal keymap
+    normal: bool,
+    pub outbox: Vec<Outgoing>,
+    sends: u64,
+    /// fetches that have failed since the last one that succeeded
+    failures: u32,
+    /// when a fetch last succeeded
+    synced: Option<DateTime<Utc>>,
+    /// refreshes are held off until then while fetches are failing
+    retry_at: Option<DateTime<Utc>>,
+    /// the newest error or notice, how it is shown, and when it stops
+    /// being shown
+    toast: Option<(String, Role, DateTime<Utc>)>,
+    pub time_format: TimeFormat,
+    /// where the current time comes from, so tests can fix it
+    pub clock: fn() -> DateTime<Utc>,
+}
+impl State {
+    pub fn new(time_format: TimeFormat, keymap: Keymap) -> Self {
+        State {
+            effects: Vec::new(),
+            layout: None,
+            focus: Selected::Sidebar,
+            alt: false,
+            alt_until: None,
+            user_data: Vec::new(),
+            users_pending: false,
+            users_fetched: false,
+            bumped: Vec::new(),
+            notify: Notify::default(),
+            title_unreads: None,
+            messages: Vec::new(),
+            messages_pending: false,
+            fetches: 0,
+            open: None,
+            loaded: None,
+            limit: PAGE_SIZE,
+            complete: false,
+            scroll: 0,
+            search: None,
+            searches: Vec::new(),
+            filter: String::new(),
+            filtering: false,
+            visible: Vec::new(),
+            selected: 0,
+            sidebar_top: 0,
+            composer: Composer::default(),
+            drafts: Drafts::new(),
+            saved_drafts: Drafts::new(),
+            sent: Vec::new(),
+            recall: History::default(),
+            dialog: None,
+            overlay: None,
+            prompt: None,
+            commands: Vec::new(),
+            keymap,
+            normal: false,
+            outbox: Vec::new(),
+            sends: 0,
+            failures: 0,
+            synced: None,
+            retry_at: None,
+            toast: None,
+            time_format,
+            clock: Utc::now,
+        }
+    }
+    /// Applies `event`, returning the effects it calls for in the order
+    /// they should be carried out.
+    pub fn update(&mut self, event: Event) -> Vec<Effect> {
+        match event {
+            Event::Key(key) => {
+                let now = (self.clock)();
+                if self.alt_until.map_or(false, |until| now > until) {
+                    self.alt = false;
+                }
+                let escape = key == Input::Character('\x1b');
+                // an escape that leaves a mode or closes something is only
+                // half of an alt key if the other half follows at once
+                let acts = escape && self.escape_acts();
+                self.key(key);
+                self.alt = escape;
+                self.alt_until = Some(now + Duration::milliseconds(ESCAPE_DELAY)).filter(|_| acts);
+            }
+            Event::Mouse(_) if self.dialog.is_some() || self.overlay.is_some() => (),
+            Event::Mouse(_) if self.prompt.is_some() => (),
+            Event::Mouse(event) => self.mouse(event),
+            Event::Resize(rows, cols) => self.resize(rows, cols),
+            Event::Tick => self.tick(),
+            event => self.handle(event),
+        }
+        std::mem::take(&mut self.effects)
+    }
+    /// Whether an escape does anything where it is typed, rather than only
+    /// making the next key alt.
+    fn escape_acts(&self) -> bool {
+        if self.overlay.is_some() || self.dialog.is_some() || self.prompt.is_some() {
+            return true;
+        }
+        let key = Key::new(Input::Character('\x1b'), self.alt);
+        self.filtering
+            || self.keymap.action(self.context(), key).is_some()
+            || self.keymap.action(Context::Global, key).is_some()
+    }
+    /// Looks a key up in the keymap, first for whatever has focus and then
+    /// globally, and only then without alt. In normal mode the composer's
+    /// own keys, with or without alt, come before global alt bindings, as an
+    /// alt key there is more likely an escape from insert mode. Keys bound
+    /// to nothing are typed, where text can be typed.
+    fn key(&mut self, input: Input) {
+        if input == Input::KeyAbort {
+            return self.quit();
+        }
+        if self.overlay.is_some() {
+            return self.close_overlay();
+        }
+        if self.dialog.is_some() {
+            return self.dialog_key(input);
+        }
+        if self.prompt.is_some() {
+            return self.prompt_key(input);
+        }
+        let key = Key::new(input, self.alt);
+        let context = self.context();
+        // text typed into the filter is never a command
+        let in_context = !(self.filtering && key.is_text());
+        // nor is text typed elsewhere, unless it is bound with alt
+        let global = !(self.typing() && key.is_text());
+        let find = |lookup: fn(&Keymap, Context, Key) -> Option<Action>, context, allowed| {
+            if allowed {
+                lookup(&self.keymap, context, key)
+            } else {
+                None
+            }
+        };
+        let modal = context == Context::Normal;
+        let action = find(Keymap::bound, context, in_context)
+            .or_else(|| find(Keymap::action, context, in_context && modal))
+            .or_else(|| find(Keymap::bound, Context::Global, global || key.alt))
+            .or_else(|| find(Keymap::action, context, in_context))
+            .or_else(|| find(Keymap::action, Context::Global, global));
+        match action {
+            Some(action) => self.act(action),
+            None if self.filtering => self.filter_key(input),
+            None if context == Context::Insert => self.type_key(input, self.alt),
+            None => (),
+        }
+    }
+    /// Where keys are looked up before the global bindings.
+    fn context(&self) -> Context {
+        match self.focus {
+            Selected::Sidebar => Context::Sidebar,
+            Selected::Feed => Context::Feed,
+            Selected::Namebar => Context::Namebar,
+            Selected::Input if self.normal => Context::Normal,
+            Selected::Input => Context::Insert,
+        }
+    }
+    /// Carries out an action on whatever has focus.
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.quit(),
+            Action::NextPane => self.next_pane(),
+            Action::PrevPane => {
+                let mut prev = self.focus;
+                prev.rotate_back();
+                self.focus_pane(prev);
+            }
+            Action::Help => self.show_overlay(Overlay::Help),
+            Action::NextUnread => self.next_unread(),
+            Action::Command => {
+                self.prompt = Some(Prompt::default());
+                self.draw_status();
+            }
+            action => match self.focus {
+                Selected::Input => self.composer_action(action),
+                Selected::Feed => self.feed_action(action),
+                Selected::Sidebar => self.sidebar_action(action),
+                Selected::Namebar => self.namebar_action(action),
+            },
+        }
+    }
+    fn show_overlay(&mut self, overlay: Overlay) {
+        self.overlay = Some(overlay);
+        self.draw_overlay();
+    }
+    fn close_overlay(&mut self) {
+        self.overlay = None;
+        self.redraw();
+    }
+    /// Draws the overlay: the bindings that apply to whatever has focus, or
+    /// a contact's onion address and unread count.
+    fn draw_overlay(&mut self) {
+        let overlay = match self.overlay {
+            Some(Overlay::Help) => {
+                let contexts: &[Context] = match self.focus {
+                    Selected::Sidebar => &[Context::Sidebar, Context::Global],
+                    Selected::Feed => &[Context::Feed, Context::Global],
+                    Selected::Namebar => &[Context::Namebar, Context::Global],
+                    Selected::Input if self.keymap.modal() => {
+                        &[Context::Insert, Context::Normal, Context::Global]
+                    }
+                    Selected::Input => &[Context::Insert, Context::Global],
+                };
+                Some(("keys".to_owned(), self.keymap.help(contexts)))
+            }
+            Some(Overlay::Profile(id)) => self.user_data.iter().find(|u| u.id == id).map(|user| {
+                let details = vec![
+                    (
+                        "onion".to_owned(),
+                        cupslib::pubkey_to_onion(&user.id).unwrap_or_default(),
+                    ),
+                    ("unread".to_owned(), user.unreads.to_string()),
+                ];
+                ("profile".to_owned(), vec![(display_name(user), details)])
+            }),
+            None => None,
+        };
+        self.draw(Render::Overlay(overlay));
+    }
+    fn quit(&mut self) {
+        self.save_drafts();
+        self.effects.push(Effect::Quit);
+    }
+    /// Starts from the drafts left from the last run.
+    pub fn load_drafts(&mut self, drafts: Drafts) {
+        self.saved_drafts = drafts.clone();
+        self.drafts = drafts;
+    }
+    /// Saves the drafts, the open conversation's included, if they changed
+    /// since they were last saved.
+    fn save_drafts(&mut self) {
+        let mut drafts = self.drafts.clone();
+        if let Some(id) = self.open.filter(|_| !self.composer.is_empty()) {
+            drafts.insert(id, self.composer.text().to_owned());
+        }
+        if drafts != self.saved_drafts {
+            self.saved_drafts = drafts.clone();
+            self.effects.push(Effect::SaveDrafts(drafts));
+        }
+    }
+    /// Refreshes, unless fetches are failing and the next retry is not due
+    /// yet. Also takes down an expired toast, keeps the sync time current
+    /// and saves the drafts.
+    fn tick(&mut self) {
+        let now = (self.clock)();
+        if self
+            .toast
+            .as_ref()
+            .map_or(false, |(_, _, until)| *until <= now)
+        {
+            self.toast = None;
+        }
+        if self.retry_at.map_or(true, |at| at <= now) {
+            self.refresh();
+        }
+        self.save_drafts();
+        // relative timestamps go stale as the clock moves on
+        if self.time_format.relative {
+            self.redraw_feed();
+        }
+        self.draw_status();
+    }
+    pub fn connection(&self) -> Connection {
+        match self.failures {
+            0 if self.synced.is_none() => Connection::Connecting,
+            0 => Connection::Connected,
+            n if n < OFFLINE_AFTER => Connection::Retrying,
+            _ => Connection::Offline,
+        }
+    }
+    fn fetch_succeeded(&mut self) {
+        self.failures = 0;
+        self.retry_at = None;
+        self.synced = Some((self.clock)());
+        self.draw_status();
+    }
+    /// Backs off from refreshing, and shows what went wrong.
+    fn fetch_failed(&mut self, what: &str, e: &Error) {
+        self.failures += 1;
+        let delay = BACKOFF << std::cmp::min(self.failures - 1, 4);
+        let delay = std::cmp::min(delay, MAX_BACKOFF);
+        self.retry_at = Some((self.clock)() + Duration::seconds(delay));
+        self.show_error(format!("{}: {}", what, e));
+    }
+    fn show_error(&mut self, text: String) {
+        self.show_toast(text, Role::Error);
+    }
+    fn show_notice(&mut self, text: String) {
+        self.show_toast(text, Role::Notice);
+    }
+    /// Shows `text` in the status bar for `TOAST_DURATION`, on one line.
+    fn show_toast(&mut self, text: String, role: Role) {
+        let until = (self.clock)() + Duration::seconds(TOAST_DURATION);
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.toast = Some((text, role, until));
+        self.draw_status();
+    }
+    /// Starts a refresh of the contact list and the selected conversation.
+    /// Requests that are still in flight are left alone rather than stacked.
+    pub fn refresh(&mut self) {
+        self.fetch_users();
+        self.fetch_messages();
+    }
+    fn fetch_users(&mut self) {
+        if self.users_pending {
+            return;
+        }
+        self.users_pending = true;
+        self.effects.push(Effect::FetchUsers);
+    }
+    fn fetch_messages(&mut self) {
+        if self.messages_pending {
+            return;
+        }
+        let id = match self.open {
+            Some(id) => id,
+            None => return,
+        };
+        self.messages_pending = true;
+        self.fetches += 1;
+        let fetch = self.fetches;
+        self.effects.push(Effect::FetchMessages {
+            id,
+            fetch,
+            limit: self.limit,
+        });
+    }
+    /// Shows the conversation with `id` in the feed, setting the draft
+    /// written to the one open aside and bringing back its own.
+    pub fn open(&mut self, id: [u8; 32]) {
+        if self.open == Some(id) {
+            return;
+        }
+        if let Some(open) = self.open {
+            let draft = self.composer.take();
+            if !draft.trim().is_empty() {
+                self.drafts.insert(open, draft);
+            }
+        }
+        self.open = Some(id);
+        self.composer
+            .set(self.drafts.remove(&id).unwrap_or_default());
+        self.recall.reset();
+        self.loaded = None;
+        self.messages.clear();
+        self.limit = PAGE_SIZE;
+        self.complete = false;
+        self.scroll = 0;
+        self.search = None;
+        self.fetch_messages();
+        self.save_drafts();
+        self.redraw_feed();
+        self.draw_input();
+        self.draw_sidebar();
+        self.draw_namebar();
+    }
+    /// Bumps the contacts whose unread count went up, other than the open
+    /// one, ringing the bell if asked to, and keeps the unread total in the
+    /// terminal title.
+    fn notice_arrivals(&mut self, users: &[UserData]) {
+        let mut arrived = false;
+        for user in users {
+            let before = self.user_data.iter().find(|u| u.id == user.id);
+            let before = before.map_or(0, |u| u.unreads);
+            if self.users_fetched && user.unreads > before && Some(user.id) != self.open {
+                self.bumped.retain(|&id| id != user.id);
+                self.bumped.insert(0, user.id);
+                arrived = true;
+            }
+        }
+        self.users_fetched = true;
+        if arrived && self.notify.bell {
+            self.draw(Render::Bell);
+        }
+        let total = users.iter().map(|u| u.unreads).sum();
+        if self.notify.title && self.title_unreads != Some(total) {
+            self.title_unreads = Some(total);
+            self.draw(Render::Title(match total {
+                0 => "cups".to_owned(),
+                n => format!("cups ({} unread)", n),
+            }));
+        }
+    }
+    /// Opens the next conversation after the open one, in sidebar order,
+    /// that has unread messages. The filter is cleared if it hides it.
+    fn next_unread(&mut self) {
+        let open = self.user_data.iter().position(|u| Some(u.id) == self.open);
+        let start = open.map_or(0, |i| i + 1);
+        let len = self.user_data.len();
+        let next = (0..len)
+            .map(|i| &self.user_data[(start + i) % len])
+            .find(|u| u.unreads > 0 && Some(u.id) != self.open)
+            .map(|u| u.id);
+        if let Some(id) = next {
+            self.select(id);
+        }
+    }
+    /// Opens the conversation with `id` and highlights the contact in the
+    /// sidebar, clearing the filter if it hides it.
+    fn select(&mut self, id: [u8; 32]) {
+        if !self.visible.iter().any(|&i| self.user_data[i].id == id) {
+            self.filtering = false;
+            self.filter.clear();
+        }
+        self.refilter(Some(id));
+        self.open(id);
+    }
+    /// Sends the composed text to the open conversation. It shows in the
+    /// feed as pending until the server answers.
+    pub fn send(&mut self) {
+        let id = match self.open {
+            Some(id) => id,
+            None => return,
+        };
+        if self.composer.is_empty() {
+            return;
+        }
+        let content = self.composer.take();
+        if self.sent.last() != Some(&content) {
+            self.sent.push(content.clone());
+        }
+        self.recall.reset();
+        self.sends += 1;
+        let seq = self.sends;
+        self.outbox.push(Outgoing {
+            seq,
+            id,
+            content: content.clone(),
+            status: SendStatus::Pending,
+        });
+        self.effects.push(Effect::Send { seq, id, content });
+        self.draw_input();
+        self.redraw_feed();
+    }
+    /// Applies an action to the composer, in either mode. Up in an empty
+    /// composer goes back through the messages sent, as does Up or Down
+    /// while doing so.
+    fn composer_action(&mut self, action: Action) {
+        let browsing = self.recall.browsing();
+        if let Action::Up | Action::Down = action {
+            if browsing || (action == Action::Up && self.composer.is_empty()) {
+                let older = action == Action::Up;
+                if self.recall.browse(&mut self.composer, &self.sent, older) {
+                    self.draw_input();
+                }
+                return;
+            }
+        }
+        self.recall.reset();
+        let composer = &mut self.composer;
+        match action {
+            Action::Send => return self.send(),
+            Action::Newline => composer.insert('\n'),
+            Action::Up => composer.up(),
+            Action::Down => composer.down(),
+            Action::Left => composer.left(),
+            Action::Right => composer.right(),
+            Action::LineStart => composer.home(),
+            Action::LineEnd => composer.end(),
+            Action::DeleteChar => composer.delete(),
+            Action::Edit => {
+                let draft = composer.text().to_owned();
+                return self.draw(Render::Edit(draft));
+            }
+            Action::NormalMode => self.normal = true,
+            Action::InsertMode => self.normal = false,
+            Action::Append => {
+                composer.right();
+                self.normal = false;
+            }
+            Action::InsertAtStart => {
+                composer.home();
+                self.normal = false;
+            }
+            Action::AppendAtEnd => {
+                composer.end();
+                self.normal = false;
+            }
+            Action::OpenLine => {
+                composer.end();
+                composer.insert('\n');
+                self.normal = false;
+            }
+            _ => return,
+        }
+        self.draw_input();
+    }
+    /// Types a key into the composer. `alt` is set when the key followed an
+    /// escape.
+    fn type_key(&mut self, key: Input, alt: bool) {
+        self.recall.reset();
+        match key {
+            Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08') if alt => {
+                self.composer.delete_word()
+            }
+            key => {
+                if !self.composer.edit(key) {
+                    return;
+                }
+            }
+        }
+        self.draw_input();
+    }
+    /// Moves focus to the next pane.
+    pub fn next_pane(&mut self) {
+        let mut next = self.focus;
+        next.rotate();
+        self.focus_pane(next);
+    }
+    pub fn focus_pane(&mut self, pane: Selected) {
+        self.focus = pane;
+        // the composer is ready to type into whenever it gains focus
+        self.normal = false;
+        self.draw(Render::Borders(pane));
+        self.redraw_feed();
+        self.draw_input();
+        self.draw_namebar();
+    }
+    /// Whether keys are currently being typed as text, rather than acting as
+    /// commands.
+    pub fn typing(&self) -> bool {
+        self.dialog.is_some()
+            || self.filtering
+            || (matches!(self.focus, Selected::Input) && !self.normal)
+    }
+    /// Opens the form for adding a contact.
+    pub fn add_contact(&mut self) {
+        self.dialog = Some(Dialog::add());
+        self.draw_dialog();
+    }
+    /// Opens the form for renaming the contact with `id`.
+    pub fn rename_contact(&mut self, id: [u8; 32]) {
+        let user = match self.user_data.iter().find(|u| u.id == id) {
+            Some(user) => user,
+            None => return,
+        };
+        let onion = match cupslib::pubkey_to_onion(&user.id) {
+            Ok(onion) => onion,
+            Err(_) => return,
+        };
+        let name = user.name.as_ref().map_or("", |name| name.as_str());
+        self.dialog = Some(Dialog::rename(onion, name));
+        self.draw_dialog();
+    }
+    /// Applies a key pressed while a dialog is open.
+    pub fn dialog_key(&mut self, key: Input) {
+        let dialog = match &mut self.dialog {
+            Some(dialog) => dialog,
+            None => return,
+        };
+        match dialog.key(key) {
+            DialogAction::None => self.draw_dialog(),
+            DialogAction::Cancel => {
+                self.dialog = None;
+                self.redraw();
+            }
+            DialogAction::Submit(onion, name) => {
+                self.draw_dialog();
+                self.effects.push(Effect::Save { onion, name });
+            }
+        }
+    }
+    /// Applies a key typed at the command or search prompt. Enter runs the
+    /// line, and Esc, or Backspace on an empty line, closes the prompt.
+    fn prompt_key(&mut self, key: Input) {
+        let prompt = match &mut self.prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Input::Character('\n') | Input::Character('\r') | Input::KeyEnter => {
+                let line = prompt.line.text().trim().to_owned();
+                let search = prompt.search;
+                self.prompt = None;
+                self.draw_status();
+                return if search {
+                    self.run_search(line)
+                } else {
+                    self.run_command(line)
+                };
+            }
+            Input::Character('\x1b') | Input::Character('\x07') => self.prompt = None,
+            Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08')
+                if prompt.line.text().is_empty() =>
+            {
+                self.prompt = None
+            }
+            Input::Character('\t') if !prompt.search => {
+                let names: Vec<String> = self.user_data.iter().map(display_name).collect();
+                prompt.complete(&names);
+            }
+            Input::KeyUp | Input::Character('\x10') | Input::KeyDown | Input::Character('\x0e') => {
+                let history = if prompt.search {
+                    &self.searches
+                } else {
+                    &self.commands
+                };
+                let older = matches!(key, Input::KeyUp | Input::Character('\x10'));
+                prompt.browse(history, older);
+            }
+            key => {
+                if !prompt.edit(key) {
+                    return;
+                }
+            }
+        }
+        self.draw_status();
+    }
+    /// Runs a line from the command prompt, keeping it in the history.
+    fn run_command(&mut self, line: String) {
+        if line.is_empty() {
+            return;
+        }
+        self.commands.retain(|c| *c != line);
+        self.commands.push(line.clone());
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(e) => return self.show_error(e.to_string()),
+        };
+        match command {
+            Command::Add { onion, name } => {
+                if let Some(e) = dialog::address_error(&onion) {
+                    return self.show_error(format!("address: {}", e));
+                }
+                if let Some(e) = dialog::name_error(&name) {
+                    return self.show_error(format!("name: {}", e));
+                }
+                self.effects.push(Effect::Save { onion, name });
+            }
+            Command::Open(name) => {
+                if let Some(id) = self.find_contact(&name) {
+                    self.select(id);
+                    self.focus_pane(Selected::Input);
+                }
+            }
+            Command::Export(path) => {
+                let user = self
+                    .open
+                    .and_then(|id| self.user_data.iter().find(|u| u.id == id));
+                match user {
+                    Some(user) => self.effects.push(Effect::Export {
+                        id: user.id,
+                        name: display_name(user),
+                        path,
+                        time_format: self.time_format.clone(),
+                    }),
+                    None => self.show_error("no conversation is open".to_owned()),
+                }
+            }
+            Command::Search(text) => self.search(&text),
+            Command::Profile(name) => {
+                if let Some(id) = self.find_contact(&name) {
+                    self.show_overlay(Overlay::Profile(id));
+                }
+            }
+            Command::Quit => self.quit(),
+        }
+    }
+    /// The contact called `name`, ignoring case, or else the one the
+    /// sidebar filter would list first for it. Says so if there is none.
+    fn find_contact(&mut self, name: &str) -> Option<[u8; 32]> {
+        let lower = name.to_lowercase();
+        let found = self
+            .user_data
+            .iter()
+            .find(|u| display_name(u).to_lowercase() == lower)
+            .or_else(|| {
+                let best = filter::filter_users(&self.user_data, name);
+                best.first().map(|&i| &self.user_data[i])
+            })
+            .map(|u| u.id);
+        if found.is_none() {
+            self.show_error(format!("no contact matches `{}`", name));
+        }
+        found
+    }
+    /// Searches the feed for a line from the search prompt, keeping it in
+    /// the history.
+    fn run_search(&mut self, line: String) {
+        if line.is_empty() {
+            return;
+        }
+        self.searches.retain(|s| *s != line);
+        self.searches.push(line.clone());
+        self.search(&line);
+    }
+    /// Highlights the matches of `query` in the feed and moves to the
+    /// newest, focusing the feed.
+    fn search(&mut self, query: &str) {
+        self.search = Some(FeedSearch {
+            query: query.to_owned(),
+            current: None,
+            looking: false,
+            reveal: false,
+        });
+        self.focus_pane(Selected::Feed);
+        self.next_match(true);
+    }
+    /// Every match of the search in the loaded messages, oldest first, as
+    /// its message counted back from the newest and which of its matches
+    /// it is.
+    fn matches(&self, query: &str) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        for (back, msg) in self.messages.iter().rev().enumerate().rev() {
+            let found = search::find(&text::normalize(msg.content.as_str()), query);
+            matches.extend((0..found.len()).map(|i| (back, i)));
+        }
+        matches
+    }
+    /// Moves the search to the next match that is older than the current
+    /// one, or newer. Once the loaded messages have no older matches, an
+    /// older page is fetched to look in, until the conversation runs out.
+    fn next_match(&mut self, older: bool) {
+        let (query, current) = match &self.search {
+            Some(search) => (search.query.clone(), search.current),
+            None => return,
+        };
+        let matches = self.matches(&query);
+        let at = current.and_then(|c| matches.iter().position(|&m| m == c));
+        let next = match (at, older) {
+            (None, true) => matches.len().checked_sub(1),
+            (None, false) => None,
+            (Some(i), true) => i.checked_sub(1),
+            (Some(i), false) => Some(i + 1).filter(|&i| i < matches.len()),
+        };
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+        match next {
+            Some(i) => {
+                search.current = Some(matches[i]);
+                search.reveal = true;
+            }
+            None if older && !self.complete => {
+                search.looking = true;
+                // a fetch in flight is waited for, so the page it brings is
+                // not taken for the whole conversation
+                if !self.messages_pending {
+                    self.limit += PAGE_SIZE;
+                    self.fetch_messages();
+                }
+            }
+            None => {
+                let what = match (matches.is_empty(), older) {
+                    (true, _) => "no messages",
+                    (false, true) => "no older messages",
+                    (false, false) => "no newer messages",
+                };
+                self.show_notice(format!("{} contain `{}`", what, query));
+            }
+        }
+        self.redraw_feed();
+    }
+    fn clear_search(&mut self) {
+        if self.search.take().is_some() {
+            self.redraw_feed();
+        }
+    }
+    /// Applies an action while the namebar is focused.
+    fn namebar_action(&mut self, action: Action) {
+        match action {
+            Action::AddContact => self.add_contact(),
+            Action::RenameContact => {
+                if let Some(id) = self.open {
+                    self.rename_contact(id);
+                }
+            }
+            _ => (),
+        }
+    }
+    /// The contact highlighted in the sidebar.
+    pub fn selected_user(&self) -> Option<&UserData> {
+        self.visible.get(self.selected).map(|&i| &self.user_data[i])
+    }
+    /// Recomputes which contacts the sidebar lists, keeping the same contact
+    /// highlighted if it is still listed.
+    fn refilter(&mut self, keep: Option<[u8; 32]>) {
+        self.visible = filter::filter_users(&self.user_data, &self.filter);
+        let user_data = &self.user_data;
+        self.selected = keep
+            .and_then(|id| self.visible.iter().position(|&i| user_data[i].id == id))
+            .unwrap_or(0);
+        self.draw_sidebar();
+    }
+    /// Moves the sidebar highlight by `delta` contacts.
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        self.selected = if delta < 0 {
+            self.selected.saturating_sub(delta.unsigned_abs())
+        } else {
+            std::cmp::min(self.selected.saturating_add(delta as usize), last)
+        };
+        self.draw_sidebar();
+    }
+    /// Types a key into the filter, which narrows the list as it changes.
+    fn filter_key(&mut self, key: Input) {
+        let keep = self.selected_user().map(|u| u.id);
+        match key {
+            Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08') => {
+                if self.filter.pop().is_none() {
+                    self.filtering = false;
+                }
+            }
+            Input::Character('\x17') | Input::Character('\x15') => self.filter.clear(),
+            Input::Character(c) if !c.is_control() => self.filter.push(c),
+            _ => return,
+        }
+        self.refilter(keep);
+    }
+    /// Applies an action while the sidebar is focused. Moving the highlight
+    /// keeps the filter open, while opening a contact closes it.
+    fn sidebar_action(&mut self, action: Action) {
+        let page = self.sidebar_page() as isize;
+        match action {
+            Action::Up => self.move_selection(-1),
+            Action::Down => self.move_selection(1),
+            Action::PageUp => self.move_selection(-page),
+            Action::PageDown => self.move_selection(page),
+            Action::Top => self.move_selection(isize::MIN),
+            Action::Bottom => self.move_selection(isize::MAX),
+            Action::Open => {
+                self.filtering = false;
+                match self.selected_user().map(|u| u.id) {
+                    Some(id) => {
+                        self.open(id);
+                        self.focus_pane(Selected::Input);
+                    }
+                    None => self.draw_sidebar(),
+                }
+            }
+            Action::Filter => {
+                self.filtering = true;
+                self.draw_sidebar();
+            }
+            Action::ClearFilter if self.filtering || !self.filter.is_empty() => {
+                let keep = self.selected_user().map(|u| u.id);
+                self.filtering = false;
+                self.filter.clear();
+                self.refilter(keep);
+            }
+            Action::AddContact if !self.filtering => self.add_contact(),
+            Action::RenameContact if !self.filtering => {
+                if let Some(id) = self.selected_user().map(|u| u.id) {
+                    self.rename_contact(id);
+                }
+            }
+            _ => (),
+        }
+    }
+    /// Contacts the sidebar shows at once, leaving a row for the filter
+    /// while there is one.
+    fn sidebar_page(&self) -> usize {
+        let filter_row = (self.filtering || !self.filter.is_empty()) as i32;
+        self.layout.as_ref().map_or(1, |layout| {
+            std::cmp::max(layout.sidebar.rows - 2 - filter_row, 1) as usize
+        })
+    }
+    /// Clicking focuses a pane, and in the sidebar opens the contact under
+    /// the pointer. The wheel moves the sidebar highlight or scrolls the
+    /// feed.
+    pub fn mouse(&mut self, event: MouseEvent) {
+        let (pane, row) = match &self.layout {
+            Some(layout) => {
+                let pane = if layout.sidebar.contains(event.y, event.x) {
+                    Selected::Sidebar
+                } else if layout.topbar.contains(event.y, event.x) {
+                    Selected::Namebar
+                } else if layout.feed.contains(event.y, event.x) {
+                    Selected::Feed
+                } else if layout.input.contains(event.y, event.x) {
+                    Selected::Input
+                } else {
+                    return;
+                };
+                (pane, event.y - layout.sidebar.y - 1)
+            }
+            None => return,
+        };
+        let wheel = if event.bstate & BUTTON4_PRESSED != 0 {
+            -1
+        } else if event.bstate & BUTTON5_PRESSED != 0 {
+            1
+        } else {
+            0
+        };
+        match pane {
+            Selected::Sidebar if wheel != 0 => self.move_selection(wheel),
+            Selected::Feed if wheel < 0 => self.scroll_up(3),
+            Selected::Feed if wheel > 0 => self.scroll_down(3),
+            _ if event.bstate & (BUTTON1_PRESSED | BUTTON1_CLICKED) != 0 => {
+                if let Selected::Sidebar = pane {
+                    let index = self.sidebar_top + row as usize;
+                    if row >= 0 && row < self.sidebar_page() as i32 && index < self.visible.len() {
+                        self.selected = index;
+                        self.open(self.user_data[self.visible[index]].id);
+                    }
+                }
+                self.focus_pane(pane);
+            }
+            _ => (),
+        }
+    }
+    /// Scrolls the feed back by `lines`, fetching an older page once the
+    /// oldest loaded message comes into view.
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll += lines;
+        if self.redraw_feed() && !self.complete && !self.messages_pending {
+            self.limit += PAGE_SIZE;
+            self.fetch_messages();
+            self.redraw_feed();
+        }
+    }
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+        self.redraw_feed();
+    }
+    /// Rows of messages the feed pane shows at once.
+    fn feed_page(&self) -> usize {
+        self.layout
+            .as_ref()
+            .map_or(1, |layout| std::cmp::max(layout.feed.rows - 3, 1) as usize)
+    }
+    /// Applies an action while the feed pane is focused.
+    fn feed_action(&mut self, action: Action) {
+        match action {
+            Action::PageUp => self.scroll_up(self.feed_page()),
+            Action::PageDown => self.scroll_down(self.feed_page()),
+            Action::Up => self.scroll_up(1),
+            Action::Down => self.scroll_down(1),
+            Action::Top => self.scroll_up(usize::MAX / 2),
+            Action::Bottom => self.scroll_down(usize::MAX),
+            Action::Search => {
+                self.prompt = Some(Prompt::search());
+                self.draw_status();
+            }
+            Action::NextMatch => self.next_match(true),
+            Action::PrevMatch => self.next_match(false),
+            Action::ClearSearch => self.clear_search(),
+            _ => (),
+        }
+    }
+    /// Lays the panes out again for a terminal of `rows` by `cols`.
+    pub fn resize(&mut self, rows: i32, cols: i32) {
+        self.layout = Layout::new(rows, cols);
+        self.redraw();
+    }
+    fn draw(&mut self, render: Render) {
+        self.effects.push(Effect::Render(render));
+    }
+    /// Draws everything from scratch.
+    pub fn redraw(&mut self) {
+        self.draw(Render::Clear);
+        if self.layout.is_some() {
+            self.draw(Render::Borders(self.focus));
+            self.draw_sidebar();
+            self.draw_namebar();
+            self.redraw_feed();
+            self.draw_input();
+            self.draw_status();
+        }
+        self.draw_dialog();
+        self.draw_overlay();
+    }
+    fn draw_status(&mut self) {
+        if self.layout.is_some() {
+            self.draw(Render::Status {
+                connection: self.connection(),
+                synced: self
+                    .synced
+                    .map(|time| self.time_format.format_at(time, (self.clock)())),
+                toast: self
+                    .toast
+                    .as_ref()
+                    .map(|(text, role, _)| (text.clone(), *role)),
+                prompt: self.prompt.clone(),
+            });
+        }
+    }
+    /// Draws the dialog over the panes, if one is open.
+    fn draw_dialog(&mut self) {
+        self.draw(Render::Dialog(self.dialog.clone()));
+    }
+    fn draw_namebar(&mut self) {
+        if self.layout.is_some() {
+            let user = self
+                .open
+                .and_then(|id| self.user_data.iter().find(|u| u.id == id));
+            let hint = match self.focus {
+                Selected::Namebar => Some(self.keymap.hint(
+                    Context::Namebar,
+                    &[
+                        (Action::AddContact, "add"),
+                        (Action::RenameContact, "rename"),
+                    ],
+                )),
+                _ => None,
+            };
+            self.draw(Render::Namebar(user.cloned(), hint));
+        }
+    }
+    /// Draws the contact list, scrolled so the highlight is in view.
+    fn draw_sidebar(&mut self) {
+        let rows = self.sidebar_page();
+        if self.layout.is_none() {
+            return;
+        }
+        if self.selected < self.sidebar_top {
+            self.sidebar_top = self.selected;
+        } else if self.selected >= self.sidebar_top + rows {
+            self.sidebar_top = self.selected + 1 - rows;
+        }
+        self.sidebar_top = std::cmp::min(self.sidebar_top, self.visible.len().saturating_sub(rows));
+        let users = self
+            .visible
+            .iter()
+            .map(|&i| self.user_data[i].clone())
+            .collect();
+        let filter = if self.filtering || !self.filter.is_empty() {
+            Some((self.filter.clone(), self.filtering))
+        } else {
+            None
+        };
+        let arrived = self
+            .bumped
+            .iter()
+            .filter(|&&id| Some(id) != self.open)
+            .filter(|&&id| self.user_data.iter().any(|u| u.id == id && u.unreads > 0))
+            .cloned()
+            .collect();
+        self.draw(Render::Sidebar {
+            users,
+            top: self.sidebar_top,
+            selected: self.selected,
+            open: self.open,
+            arrived,
+            filter,
+        });
+    }
+    fn draw_input(&mut self) {
+        if self.layout.is_some() {
+            let mode = match (self.keymap.modal(), self.normal) {
+                (false, _) => None,
+                (true, false) => Some("INSERT"),
+                (true, true) => Some("NORMAL"),
+            };
+            self.draw(Render::Input {
+                composer: self.composer.clone(),
+                focused: matches!(self.focus, Selected::Input),
+                mode,
+            });
+        }
+    }
+    /// Redraws the feed, returning whether its oldest line is in view.
+    fn redraw_feed(&mut self) -> bool {
+        let (rows, cols) = match &self.layout {
+            Some(layout) => feed_size(layout),
+            None => return false,
+        };
+        let pending: Vec<&Outgoing> = self
+            .outbox
+            .iter()
+            .filter(|o| Some(o.id) == self.open)
+            .collect();
+        let mut lines = Vec::new();
+        if self.complete {
+            lines.push(FeedLine {
+                text: "start of conversation".to_owned(),
+                align: Align::Center,
+                role: Role::Muted,
+                marks: Vec::new(),
+            });
+        }
+        lines.extend(feed_lines(
+            &self.messages,
+            &pending,
+            cols,
+            &self.time_format,
+            (self.clock)(),
+            self.search.as_ref(),
+        ));
+        let max = lines.len().saturating_sub(rows);
+        self.scroll = std::cmp::min(self.scroll, max);
+        // a match moved to out of view is brought a third of the way down
+        if let Some(search) = self.search.as_mut().filter(|s| s.reveal) {
+            search.reveal = false;
+            let current = lines.iter().position(|l| l.marks.iter().any(|m| m.current));
+            if let Some(line) = current {
+                let end = lines.len() - self.scroll;
+                if line < end.saturating_sub(rows) || line >= end {
+                    let top = line.saturating_sub(rows / 3);
+                    self.scroll = std::cmp::min(lines.len().saturating_sub(top + rows), max);
+                }
+            }
+        }
+        let search = self.search.as_ref().map(|search| {
+            let matches = self.matches(&search.query);
+            let at = search
+                .current
+                .and_then(|c| matches.iter().position(|&m| m == c));
+            match at {
+                Some(i) => format!(
+                    "/{} {} of {}",
+                    search.query,
+                    matches.len() - i,
+                    matches.len()
+                ),
+                None => format!("/{} {} found", search.query, matches.len()),
+            }
+        });
+        self.draw(Render::Feed {
+            lines,
+            scroll: self.scroll,
+            focused: matches!(self.focus, Selected::Feed),
+            loading: self.messages_pending && self.limit > self.messages.len(),
+            search,
+        });
+        self.scroll == max
+    }
+    /// The messages in `messages` newer than any loaded so far.
+    fn newer<'a>(&self, messages: &'a [Message]) -> &'a [Message] {
+        let newest = match self.messages.last() {
+            Some(msg) => msg,
+            None => return &[],
+        };
+        let same = |m: &Message| {
+            m.time == newest.time
+                && m.inbound == newest.inbound
+                && m.content.as_bytes() == newest.content.as_bytes()
+        };
+        match messages.iter().rposition(same) {
+            Some(i) => &messages[i + 1..],
+            None => &[],
+        }
+    }
+    /// Feed lines taken up by messages in `messages` newer than any loaded
+    /// so far, so a feed scrolled back can stay where it is when they arrive.
+    fn lines_added(&self, messages: &[Message]) -> usize {
+        let added = self.newer(messages);
+        let now = (self.clock)();
+        match &self.layout {
+            Some(layout) => feed_lines(
+                added,
+                &[],
+                feed_size(layout).1,
+                &self.time_format,
+                now,
+                None,
+            )
+            .len(),
+            None => 0,
+        }
+    }
+    /// Takes in the result of a network request.
+    /// Failed fetches are retried rather than given up on.
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Users(res) => {
+                self.users_pending = false;
+                let users = match res {
+                    Ok(users) => users,
+                    Err(e) => return self.fetch_failed("fetching contacts failed", &e),
+                };
+                self.fetch_succeeded();
+                let keep = self.selected_user().map(|u| u.id);
+                let mut users = users.records;
+                self.notice_arrivals(&users);
+                let bumped = &self.bumped;
+                users.sort_by_key(|u| {
+                    let i = bumped.iter().position(|&id| id == u.id);
+                    i.unwrap_or(bumped.len())
+                });
+                self.user_data = users;
+                self.refilter(keep);
+                self.draw_namebar();
+                if self.open.is_none() {
+                    if let Some(id) = self.selected_user().map(|u| u.id) {
+                        self.open(id);
+                    }
+                }
+            }
+            Event::Messages(id, fetch, res) => {
+                self.messages_pending = false;
+                let msgs = match res {
+                    Ok(msgs) => msgs,
+                    Err(e) => {
+                        self.redraw_feed();
+                        return self.fetch_failed("fetching messages failed", &e);
+                    }
+                };
+                self.fetch_succeeded();
+                if Some(id) == self.open {
+                    // the server sends newest first
+                    // a page short of damaged records is not the last
+                    self.complete = msgs.received < self.limit;
+                    let mut messages = msgs.records;
+                    messages.reverse();
+                    if self.loaded != Some(id) {
+                        self.loaded = Some(id);
+                    } else {
+                        if self.scroll > 0 {
+                            self.scroll += self.lines_added(&messages);
+                        }
+                        // the current match is counted from the newest message
+                        let added = self.newer(&messages).len();
+                        if let Some(search) = &mut self.search {
+                            if let Some((back, _)) = &mut search.current {
+                                *back += added;
+                            }
+                        }
+                    }
+                    self.messages = messages;
+                    self.outbox.retain(|o| match o.status {
+                        SendStatus::Sent { after } => o.id != id || fetch <= after,
+                        _ => true,
+                    });
+                    self.redraw_feed();
+                    // an older page was fetched for the search to look in
+                    if let Some(search) = self.search.as_mut().filter(|s| s.looking) {
+                        search.looking = false;
+                        self.next_match(true);
+                    }
+                    // a send finished while this was in flight
+                    if self.outbox.iter().any(|o| match o.status {
+                        SendStatus::Sent { .. } => o.id == id,
+                        _ => false,
+                    }) {
+                        self.fetch_messages();
+                    }
+                } else {
+                    // another conversation was opened while this was in flight
+                    self.fetch_messages();
+                }
+            }
+            Event::Sent(seq, res) => {
+                if let Err(e) = &res {
+                    self.show_error(format!("sending failed: {}", e));
+                }
+                if let Some(out) = self.outbox.iter_mut().find(|o| o.seq == seq) {
+                    out.status = match res {
+                        Ok(()) => SendStatus::Sent {
+                            after: self.fetches,
+                        },
+                        Err(e) => SendStatus::Failed(e.to_string()),
+                    };
+                    if Some(out.id) == self.open {
+                        self.redraw_feed();
+                        self.fetch_messages();
+                    }
+                }
+            }
+            // saves from the prompt have no dialog to report to
+            Event::Saved(res) => match (res, &mut self.dialog) {
+                (Ok(()), Some(_)) => {
+                    self.dialog = None;
+                    self.fetch_users();
+                    self.redraw();
+                }
+                (Ok(()), None) => {
+                    self.fetch_users();
+                    self.show_notice("contact saved".to_owned());
+                }
+                (Err(e), Some(dialog)) => {
+                    dialog.status = DialogStatus::Failed(e.to_string());
+                    self.draw_dialog();
+                }
+                (Err(e), None) => self.show_error(format!("saving failed: {}", e)),
+            },
+            Event::Exported(res) => match res {
+                Ok((path, count)) => {
+                    self.show_notice(format!("exported {} messages to {}", count, path.display()))
+                }
+                Err(e) => self.show_error(format!("exporting failed: {}", e)),
+            },
+            Event::DraftsSaved(res) => {
+                if let Err(e) = res {
+                    self.show_error(format!("saving drafts failed: {}", e));
+                }
+            }
+            // the editor had the whole terminal, so everything is redrawn;
+            // the text is left in the composer to look over before sending
+            Event::Edited(res) => {
+                match res {
+                    Ok(text) => self.composer.set(text.trim_end().to_owned()),
+                    Err(e) => self.show_error(format!("editing failed: {}", e)),
+                }
+                self.redraw();
+            }
+            Event::Key(_) | Event::Mouse(_) | Event::Resize(..) | Event::Tick => (),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+pub struct FeedLine {
+    pub text: String,
+    pub align: Align,
+    pub role: Role,
+    /// where a search matched on the line
+    pub marks: Vec<Mark>,
+}
+
+/// Lays out messages (oldest first) as lines of at most `width` columns.
+/// Inbound messages hug the left edge and outbound ones the right, each
+/// under its timestamp, with a separator wherever the day changes. Matches
+/// of `search` are marked, if `msgs` are all the loaded messages.
+fn feed_lines(
+    msgs: &[Message],
+    pending: &[&Outgoing],
+    width: usize,
+    time_format: &TimeFormat,
+    now: DateTime<Utc>,
+    search: Option<&FeedSearch>,
+) -> Vec<FeedLine> {
+    let wrap_width = std::cmp::max(width * 3 / 4, 1);
+    let mut lines = Vec::new();
+    let mut day = None;
+    for (i, msg) in msgs.iter().enumerate() {
+        if let Some(time) = msg.datetime() {
+            let date = time_format.date(time);
+            if day != Some(date) {
+                day = Some(date);
+                lines.push(FeedLine {
+                    text: format!(" {} ", date.format("%A, %-d %B %Y")),
+                    align: Align::Center,
+                    role: Role::Day,
+                    marks: Vec::new(),
+                });
+            }
+        }
+        let (align, role) = if msg.inbound {
+            (Align::Left, Role::Inbound)
+        } else {
+            (Align::Right, Role::Outbound)
+        };
+        lines.push(FeedLine {
+            text: msg.format_time_at(time_format, now),
+            align,
+            role: Role::Timestamp,
+            marks: Vec::new(),
+        });
+        let wrapped = text::wrap(msg.content.as_str(), wrap_width);
+        let mut marks = match search {
+            Some(search) => {
+                let text = text::normalize(msg.content.as_str());
+                let found = search::find(&text, &search.query);
+                let current = match search.current {
+                    Some((back, j)) if back == msgs.len() - 1 - i => Some(j),
+                    _ => None,
+                };
+                search::marks(&text, &wrapped, &found, current)
+            }
+            None => Vec::new(),
+        }
+        .into_iter();
+        for text in wrapped {
+            let marks = marks.next().unwrap_or_default();
+            lines.push(FeedLine {
+                text,
+                align,
+                role,
+                marks,
+            });
+        }
+    }
+    for out in pending {
+        let (text, role) = match &out.status {
+            SendStatus::Pending => ("sending...".to_owned(), Role::Muted),
+            SendStatus::Sent { .. } => ("sent".to_owned(), Role::Muted),
+            SendStatus::Failed(e) => (format!("failed: {}", e), Role::Error),
+        };
+        lines.push(FeedLine {
+            text,
+            align: Align::Right,
+            role,
+            marks: Vec::new(),
+        });
+        for text in text::wrap(&out.content, wrap_width) {
+            lines.push(FeedLine {
+                text,
+                align: Align::Right,
+                role: Role::Outbound,
+                marks: Vec::new(),
+            });
+        }
+    }
+    lines
+}
+
+/// What a contact is called: its name, or its onion address if it has
+/// none.
+pub fn display_name(user: &UserData) -> String {
+    match &user.name {
+        Some(name) if !name.as_str().is_empty() => name.as_str().to_owned(),
+        _ => cupslib::pubkey_to_onion(&user.id).unwrap_or_default(),
+    }
+}
+
+/// Rows and columns available for text inside the feed pane's border.
+pub fn feed_size(layout: &Layout) -> (usize, usize) {
+    let (height, width) = (layout.feed.rows, layout.feed.cols);
+    (
+        std::cmp::max(height - 2, 0) as usize,
+        std::cmp::max(width - 4, 0) as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests;
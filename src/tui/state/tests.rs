@@ -0,0 +1,714 @@
+use std::cell::Cell;
+
+use chrono::TimeZone;
+use cupslib::{Text, Zone};
+
+use super::*;
+
+thread_local! {
+    /// seconds since the epoch, as far as the state can tell
+    static NOW: Cell<i64> = Cell::new(0);
+}
+
+fn now() -> DateTime<Utc> {
+    Utc.timestamp_opt(NOW.with(Cell::get), 0).unwrap()
+}
+
+fn advance(secs: i64) {
+    NOW.with(|now| now.set(now.get() + secs));
+}
+
+fn state() -> State {
+    let mut state = State::new(
+        TimeFormat {
+            zone: Zone::Utc,
+            ..TimeFormat::default()
+        },
+        Keymap::default(),
+    );
+    state.clock = now;
+    state.update(Event::Resize(24, 80));
+    state
+}
+
+fn user(n: u8, name: &str) -> UserData {
+    UserData {
+        id: [n; 32],
+        name: Some(Text::Utf8(name.to_owned())),
+        unreads: 0,
+    }
+}
+
+fn unread(n: u8, name: &str, unreads: u64) -> UserData {
+    UserData {
+        unreads,
+        ..user(n, name)
+    }
+}
+
+fn users(records: Vec<UserData>) -> Event {
+    Event::Users(Ok(Decoded {
+        received: records.len(),
+        records,
+        warnings: Vec::new(),
+    }))
+}
+
+fn messages(id: [u8; 32], fetch: u64, records: Vec<Message>) -> Event {
+    Event::Messages(
+        id,
+        fetch,
+        Ok(Decoded {
+            received: records.len(),
+            records,
+            warnings: Vec::new(),
+        }),
+    )
+}
+
+fn key(c: char) -> Event {
+    Event::Key(Input::Character(c))
+}
+
+fn typed(state: &mut State, text: &str) -> Vec<Effect> {
+    text.chars().flat_map(|c| state.update(key(c))).collect()
+}
+
+/// The effects that are not drawing.
+fn requests(effects: Vec<Effect>) -> Vec<Effect> {
+    effects
+        .into_iter()
+        .filter(|e| !matches!(e, Effect::Render(_)))
+        .collect()
+}
+
+/// A state with alice, bob and zoë listed and alice's conversation
+/// loaded.
+fn loaded() -> State {
+    let mut state = state();
+    state.update(Event::Tick);
+    state.update(users(vec![
+        user(1, "alice"),
+        user(2, "bob"),
+        user(3, "zoë"),
+    ]));
+    state.update(messages([1; 32], 1, Vec::new()));
+    state
+}
+
+fn selected(state: &State) -> Option<[u8; 32]> {
+    state.selected_user().map(|u| u.id)
+}
+
+#[test]
+fn first_contact_opens_when_the_list_arrives() {
+    let mut state = state();
+    let effects = requests(state.update(Event::Tick));
+    assert!(matches!(effects.as_slice(), [Effect::FetchUsers]));
+    let effects = requests(state.update(users(vec![user(1, "alice"), user(2, "bob")])));
+    match effects.as_slice() {
+        [Effect::FetchMessages { id, limit, .. }] => {
+            assert_eq!(*id, [1; 32]);
+            assert_eq!(*limit, PAGE_SIZE);
+        }
+        _ => panic!("expected a single fetch"),
+    }
+    assert_eq!(state.open, Some([1; 32]));
+}
+
+#[test]
+fn refresh_does_not_stack_requests() {
+    let mut state = state();
+    assert_eq!(requests(state.update(Event::Tick)).len(), 1);
+    assert!(requests(state.update(Event::Tick)).is_empty());
+    state.update(users(vec![user(1, "alice")]));
+    state.update(messages([1; 32], 1, Vec::new()));
+    assert_eq!(requests(state.update(Event::Tick)).len(), 2);
+}
+
+#[test]
+fn keys_move_the_selection_within_bounds() {
+    let mut state = loaded();
+    typed(&mut state, "jjjj");
+    assert_eq!(selected(&state), Some([3; 32]));
+    typed(&mut state, "k");
+    assert_eq!(selected(&state), Some([2; 32]));
+    state.update(Event::Key(Input::KeyHome));
+    assert_eq!(selected(&state), Some([1; 32]));
+    state.update(Event::Key(Input::KeyUp));
+    assert_eq!(selected(&state), Some([1; 32]));
+    state.update(Event::Key(Input::KeyEnd));
+    assert_eq!(selected(&state), Some([3; 32]));
+    // moving the highlight does not change the open conversation
+    assert_eq!(state.open, Some([1; 32]));
+}
+
+#[test]
+fn enter_opens_the_highlighted_contact_and_focuses_the_composer() {
+    let mut state = loaded();
+    typed(&mut state, "j");
+    let effects = requests(state.update(key('\n')));
+    assert!(matches!(
+        effects.as_slice(),
+        [Effect::FetchMessages { id, .. }] if *id == [2; 32]
+    ));
+    assert_eq!(state.open, Some([2; 32]));
+    assert!(state.messages.is_empty());
+    assert!(matches!(state.focus, Selected::Input));
+}
+
+#[test]
+fn tab_cycles_focus() {
+    let mut state = loaded();
+    let order: Vec<Selected> = (0..4)
+        .map(|_| {
+            state.update(key('\t'));
+            state.focus
+        })
+        .collect();
+    assert!(matches!(
+        order.as_slice(),
+        [
+            Selected::Input,
+            Selected::Feed,
+            Selected::Namebar,
+            Selected::Sidebar
+        ]
+    ));
+}
+
+#[test]
+fn selection_follows_the_contact_across_refreshes() {
+    let mut state = loaded();
+    typed(&mut state, "j");
+    assert_eq!(selected(&state), Some([2; 32]));
+    state.update(users(vec![
+        user(3, "zoë"),
+        user(2, "bob"),
+        user(1, "alice"),
+    ]));
+    assert_eq!(selected(&state), Some([2; 32]));
+    assert_eq!(state.selected, 1);
+    // once the contact is gone the highlight goes back to the top
+    state.update(users(vec![user(3, "zoë"), user(1, "alice")]));
+    assert_eq!(selected(&state), Some([3; 32]));
+}
+
+#[test]
+fn filter_narrows_the_list_and_keeps_the_selection() {
+    let mut state = loaded();
+    typed(&mut state, "/oë");
+    assert!(state.filtering);
+    assert_eq!(state.visible.len(), 1);
+    assert_eq!(selected(&state), Some([3; 32]));
+    // a refresh while filtering keeps both the filter and the highlight
+    state.update(users(vec![
+        user(3, "zoë"),
+        user(4, "dave"),
+        user(1, "alice"),
+    ]));
+    assert_eq!(state.visible.len(), 1);
+    assert_eq!(selected(&state), Some([3; 32]));
+    state.update(key('\x1b'));
+    assert!(!state.filtering);
+    assert!(state.filter.is_empty());
+    assert_eq!(state.visible.len(), 3);
+    assert_eq!(selected(&state), Some([3; 32]));
+}
+
+#[test]
+fn q_quits_unless_typing() {
+    let mut state = loaded();
+    typed(&mut state, "/");
+    assert!(requests(typed(&mut state, "q")).is_empty());
+    assert_eq!(state.filter, "q");
+    state.update(key('\x1b'));
+    let effects = requests(typed(&mut state, "q"));
+    assert!(matches!(effects.as_slice(), [Effect::Quit]));
+}
+
+#[test]
+fn composing_and_sending() {
+    let mut state = loaded();
+    state.update(key('\n'));
+    assert!(matches!(state.focus, Selected::Input));
+    // q is text while composing
+    assert!(requests(typed(&mut state, "hi q")).is_empty());
+    // alt+enter starts a new line instead of sending
+    state.update(key('\x1b'));
+    state.update(key('\n'));
+    typed(&mut state, "there");
+    assert_eq!(state.composer.text(), "hi q\nthere");
+    state.update(Event::Key(Input::KeyLeft));
+    state.update(Event::Key(Input::KeyBackspace));
+    assert_eq!(state.composer.text(), "hi q\nthee");
+    let effects = requests(state.update(key('\n')));
+    match effects.as_slice() {
+        [Effect::Send { id, content, .. }] => {
+            assert_eq!(*id, [1; 32]);
+            assert_eq!(content, "hi q\nthee");
+        }
+        _ => panic!("expected a send"),
+    }
+    assert!(state.composer.text().is_empty());
+    assert_eq!(state.outbox.len(), 1);
+    assert!(matches!(state.outbox[0].status, SendStatus::Pending));
+}
+
+#[test]
+fn blank_messages_are_not_sent() {
+    let mut state = loaded();
+    state.update(key('\n'));
+    typed(&mut state, "   ");
+    assert!(requests(state.update(key('\n'))).is_empty());
+    assert!(state.outbox.is_empty());
+}
+
+#[test]
+fn sent_messages_stay_until_a_later_fetch_returns() {
+    let mut state = loaded();
+    state.update(key('\n'));
+    typed(&mut state, "hello");
+    let seq = match requests(state.update(key('\n'))).as_slice() {
+        [Effect::Send { seq, .. }] => *seq,
+        _ => panic!("expected a send"),
+    };
+    let fetch = match requests(state.update(Event::Sent(seq, Ok(())))).as_slice() {
+        [Effect::FetchMessages { fetch, .. }] => *fetch,
+        _ => panic!("expected a fetch"),
+    };
+    assert!(matches!(state.outbox[0].status, SendStatus::Sent { .. }));
+    // a fetch that started before the send finished does not count
+    state.update(messages([1; 32], fetch - 1, Vec::new()));
+    assert_eq!(state.outbox.len(), 1);
+    let echo = Message {
+        inbound: false,
+        time: 0,
+        content: Text::Utf8("hello".to_owned()),
+    };
+    state.update(messages([1; 32], fetch, vec![echo]));
+    assert!(state.outbox.is_empty());
+    assert_eq!(state.messages.len(), 1);
+}
+
+#[test]
+fn failed_sends_keep_the_error() {
+    let mut state = loaded();
+    state.update(key('\n'));
+    typed(&mut state, "hello");
+    state.update(key('\n'));
+    let seq = state.outbox[0].seq;
+    state.update(Event::Sent(
+        seq,
+        Err(failure::err_msg("connection refused")),
+    ));
+    match &state.outbox[0].status {
+        SendStatus::Failed(e) => assert_eq!(e, "connection refused"),
+        _ => panic!("expected a failure"),
+    }
+}
+
+#[test]
+fn small_terminals_have_no_layout() {
+    let mut state = loaded();
+    assert!(state.layout.is_some());
+    state.update(Event::Resize(MIN_SIZE.0 - 1, 80));
+    assert!(state.layout.is_none());
+    state.update(Event::Resize(MIN_SIZE.0, MIN_SIZE.1));
+    assert!(state.layout.is_some());
+}
+
+#[test]
+fn failed_fetches_are_retried_with_backoff() {
+    let mut state = state();
+    state.update(Event::Tick);
+    assert_eq!(state.connection(), Connection::Connecting);
+    let fail = || Event::Users(Err(failure::err_msg("connection refused")));
+    state.update(fail());
+    assert_eq!(state.connection(), Connection::Retrying);
+    assert!(state.toast.is_some());
+    // no refresh until the backoff has passed
+    assert!(requests(state.update(Event::Tick)).is_empty());
+    advance(BACKOFF);
+    let effects = requests(state.update(Event::Tick));
+    assert!(matches!(effects.as_slice(), [Effect::FetchUsers]));
+    state.update(fail());
+    advance(BACKOFF);
+    assert!(requests(state.update(Event::Tick)).is_empty());
+    advance(BACKOFF);
+    assert_eq!(requests(state.update(Event::Tick)).len(), 1);
+    state.update(fail());
+    assert_eq!(state.connection(), Connection::Offline);
+    advance(BACKOFF * 4);
+    assert_eq!(requests(state.update(Event::Tick)).len(), 1);
+    // the toast has expired by now
+    assert!(state.toast.is_none());
+    state.update(users(vec![user(1, "alice")]));
+    assert_eq!(state.connection(), Connection::Connected);
+    assert_eq!(state.synced, Some(now()));
+}
+
+#[test]
+fn vim_keymap_makes_the_composer_modal() {
+    let mut state = loaded();
+    state.keymap = Keymap::vim();
+    state.update(key('\n'));
+    typed(&mut state, "helo");
+    state.update(key('\x1b'));
+    // letters are commands in normal mode
+    typed(&mut state, "hhil");
+    assert_eq!(state.composer.text(), "hello");
+    state.update(key('\x1b'));
+    let effects = requests(typed(&mut state, "q"));
+    // the unsent text is kept for next time
+    assert!(matches!(
+        effects.as_slice(),
+        [Effect::SaveDrafts(_), Effect::Quit]
+    ));
+    typed(&mut state, "Aq");
+    assert_eq!(state.composer.text(), "helloq");
+    state.update(key('\x1b'));
+    let effects = requests(state.update(key('\n')));
+    assert!(matches!(effects.as_slice(), [Effect::Send { .. }]));
+}
+
+#[test]
+fn shift_tab_goes_back_a_pane() {
+    let mut state = loaded();
+    state.update(Event::Key(Input::KeySTab));
+    assert!(matches!(state.focus, Selected::Namebar));
+    state.update(Event::Key(Input::KeySTab));
+    assert!(matches!(state.focus, Selected::Feed));
+}
+
+#[test]
+fn any_key_closes_the_help() {
+    let mut state = loaded();
+    typed(&mut state, "?");
+    assert_eq!(state.overlay, Some(Overlay::Help));
+    // the key that closes it does nothing else
+    assert!(requests(typed(&mut state, "q")).is_empty());
+    assert_eq!(state.overlay, None);
+    assert_eq!(requests(typed(&mut state, "q")).len(), 1);
+}
+
+#[test]
+fn arriving_messages_bump_the_contact() {
+    let mut state = state();
+    state.notify = Notify {
+        bell: true,
+        title: true,
+    };
+    let order = |state: &State| state.user_data.iter().map(|u| u.id[0]).collect::<Vec<_>>();
+    let bells = |effects: &[Effect]| {
+        let bell = |e: &&Effect| matches!(e, Effect::Render(Render::Bell));
+        effects.iter().filter(bell).count()
+    };
+    let title = |effects: &[Effect]| {
+        effects.iter().find_map(|e| match e {
+            Effect::Render(Render::Title(title)) => Some(title.clone()),
+            _ => None,
+        })
+    };
+    state.update(Event::Tick);
+    // what was unread before the first fetch is not news
+    let effects = state.update(users(vec![
+        user(1, "alice"),
+        unread(2, "bob", 1),
+        user(3, "zoë"),
+    ]));
+    assert_eq!(bells(&effects), 0);
+    assert_eq!(title(&effects).as_deref(), Some("cups (1 unread)"));
+    // nor is anything for the open conversation
+    let effects = state.update(users(vec![
+        unread(1, "alice", 1),
+        unread(2, "bob", 1),
+        user(3, "zoë"),
+    ]));
+    assert_eq!(bells(&effects), 0);
+    assert_eq!(order(&state), [1, 2, 3]);
+    let effects = state.update(users(vec![
+        unread(1, "alice", 1),
+        unread(2, "bob", 1),
+        unread(3, "zoë", 2),
+    ]));
+    assert_eq!(bells(&effects), 1);
+    assert_eq!(title(&effects).as_deref(), Some("cups (4 unread)"));
+    assert_eq!(order(&state), [3, 1, 2]);
+    // the most recent goes first, and stays bumped once read
+    state.update(users(vec![
+        unread(1, "alice", 1),
+        unread(2, "bob", 2),
+        user(3, "zoë"),
+    ]));
+    assert_eq!(order(&state), [2, 3, 1]);
+    let effects = state.update(users(vec![
+        unread(1, "alice", 1),
+        unread(2, "bob", 2),
+        user(3, "zoë"),
+    ]));
+    assert_eq!(bells(&effects), 0);
+    assert_eq!(title(&effects), None);
+}
+
+#[test]
+fn alt_a_opens_the_next_unread_conversation() {
+    let mut state = loaded();
+    let alt_a = |state: &mut State| {
+        state.update(key('\x1b'));
+        state.update(key('a'));
+    };
+    state.update(users(vec![
+        user(1, "alice"),
+        unread(2, "bob", 1),
+        unread(3, "zoë", 1),
+    ]));
+    alt_a(&mut state);
+    assert_eq!(state.open, Some([3; 32]));
+    assert_eq!(selected(&state), Some([3; 32]));
+    // from the composer too, where it types nothing
+    state.focus_pane(Selected::Input);
+    alt_a(&mut state);
+    assert_eq!(state.open, Some([2; 32]));
+    assert_eq!(state.composer.text(), "");
+    // a filter hiding the next one is cleared
+    state.focus_pane(Selected::Sidebar);
+    typed(&mut state, "/bob");
+    state.update(key('\n'));
+    state.update(users(vec![
+        user(1, "alice"),
+        unread(2, "bob", 1),
+        unread(3, "zoë", 1),
+    ]));
+    alt_a(&mut state);
+    assert_eq!(state.open, Some([3; 32]));
+    assert!(state.filter.is_empty());
+}
+
+#[test]
+fn escapes_that_do_something_are_not_alt_for_long() {
+    let mut state = loaded();
+    state.update(users(vec![
+        user(1, "alice"),
+        unread(2, "bob", 1),
+        unread(3, "zoë", 1),
+    ]));
+    // in vim, leaving insert mode and appending is not alt-a
+    state.keymap = Keymap::vim();
+    state.focus_pane(Selected::Input);
+    typed(&mut state, "hi\x1bab");
+    assert_eq!(state.composer.text(), "hib");
+    assert_eq!(state.open, Some([1; 32]));
+    // nor is clearing the filter and adding a contact, given a moment
+    state.keymap = Keymap::default();
+    state.focus_pane(Selected::Sidebar);
+    typed(&mut state, "/zo\x1b");
+    assert!(state.filter.is_empty());
+    advance(1);
+    typed(&mut state, "a");
+    assert!(state.dialog.is_some());
+    assert_eq!(state.open, Some([1; 32]));
+}
+
+#[test]
+fn commands_run_from_the_prompt() {
+    let mut state = loaded();
+    let run = |state: &mut State, line: &str| {
+        typed(state, ":");
+        let mut effects = typed(state, line);
+        effects.extend(state.update(key('\n')));
+        requests(effects)
+    };
+    run(&mut state, "open zo");
+    assert_eq!(state.open, Some([3; 32]));
+    assert!(matches!(state.focus, Selected::Input));
+    // in the composer, `:` is typed, so the prompt needs M-x
+    typed(&mut state, "\x1bx");
+    assert!(state.prompt.is_some());
+    state.update(key('\x1b'));
+    assert!(state.prompt.is_none());
+    state.focus_pane(Selected::Sidebar);
+    let onion = cupslib::pubkey_to_onion(&[4; 32]).unwrap();
+    let effects = run(&mut state, &format!("add {} dave", onion));
+    assert!(matches!(&effects[..], [Effect::Save { name, .. }] if name == "dave"));
+    assert!(run(&mut state, "add nope dave").is_empty());
+    assert!(state.toast.as_ref().unwrap().0.starts_with("address: "));
+    run(&mut state, "profile BOB");
+    assert_eq!(state.overlay, Some(Overlay::Profile([2; 32])));
+    state.update(key('x'));
+    let effects = run(&mut state, "export ~/zoe.txt");
+    assert!(
+        matches!(&effects[..], [Effect::Export { id, name, .. }] if *id == [3; 32] && name == "zoë")
+    );
+    assert!(matches!(&run(&mut state, "q")[..], [Effect::Quit]));
+    // each line once, most recent last
+    run(&mut state, "open zo");
+    assert_eq!(state.commands.len(), 6);
+    assert_eq!(state.commands.last().unwrap(), "open zo");
+    state.focus_pane(Selected::Sidebar);
+    typed(&mut state, ":");
+    state.update(Event::Key(Input::KeyUp));
+    assert_eq!(state.prompt.as_ref().unwrap().line.text(), "open zo");
+}
+
+#[test]
+fn drafts_stay_with_their_conversation() {
+    let mut state = loaded();
+    let saved = |effects: Vec<Effect>| {
+        effects.into_iter().find_map(|e| match e {
+            Effect::SaveDrafts(drafts) => Some(drafts),
+            _ => None,
+        })
+    };
+    state.focus_pane(Selected::Input);
+    typed(&mut state, "half a thought");
+    // saved along with everything else on the next tick
+    let drafts = saved(state.update(Event::Tick)).unwrap();
+    assert_eq!(drafts[&[1; 32]], "half a thought");
+    assert!(saved(state.update(Event::Tick)).is_none());
+    state.open([2; 32]);
+    assert_eq!(state.composer.text(), "");
+    typed(&mut state, "for bob");
+    state.open([1; 32]);
+    assert_eq!(state.composer.text(), "half a thought");
+    assert_eq!(state.drafts[&[2; 32]], "for bob");
+    // sending drops the draft, and quitting saves first
+    state.update(key('\n'));
+    let effects = requests(state.update(Event::Key(Input::KeyAbort)));
+    assert!(matches!(effects.last(), Some(Effect::Quit)));
+    let drafts = saved(effects).unwrap();
+    assert!(!drafts.contains_key(&[1; 32]));
+    assert_eq!(drafts[&[2; 32]], "for bob");
+}
+
+#[test]
+fn up_in_an_empty_composer_recalls_sent_messages() {
+    let mut state = loaded();
+    state.focus_pane(Selected::Input);
+    for text in ["first", "second"].iter() {
+        typed(&mut state, text);
+        state.update(key('\n'));
+    }
+    typed(&mut state, "line one");
+    state.update(Event::Key(Input::KeyUp));
+    assert_eq!(state.composer.text(), "line one");
+    state.composer.take();
+    state.update(Event::Key(Input::KeyUp));
+    assert_eq!(state.composer.text(), "second");
+    state.update(Event::Key(Input::KeyUp));
+    state.update(Event::Key(Input::KeyUp));
+    assert_eq!(state.composer.text(), "first");
+    state.update(Event::Key(Input::KeyDown));
+    // editing what was brought back stops going through them
+    typed(&mut state, " again");
+    state.update(Event::Key(Input::KeyDown));
+    assert_eq!(state.composer.text(), "second again");
+    state.update(key('\n'));
+    assert_eq!(state.sent, ["first", "second", "second again"]);
+}
+
+#[test]
+fn searching_the_feed_pages_back_for_older_matches() {
+    // every twentieth message, from the sixth, is a match
+    let conversation = |n: i64| -> Vec<Message> {
+        let msg = |i: i64| Message {
+            inbound: i % 2 == 0,
+            time: i * 60,
+            content: Text::Utf8(if i % 20 == 5 {
+                format!("a Needle in {}", i)
+            } else {
+                format!("hay {}", i)
+            }),
+        };
+        (0..n).rev().map(msg).collect()
+    };
+    let mut state = state();
+    state.update(Event::Tick);
+    state.update(users(vec![user(1, "alice")]));
+    let page = conversation(60).into_iter().take(PAGE_SIZE).collect();
+    state.update(messages([1; 32], 1, page));
+    state.focus_pane(Selected::Feed);
+    typed(&mut state, "/needle\n");
+    let current = |state: &State| state.search.as_ref().unwrap().current;
+    // the newest first, counted back from the newest message
+    assert_eq!(current(&state), Some((14, 0)));
+    typed(&mut state, "n");
+    assert_eq!(current(&state), Some((34, 0)));
+    // nothing older is loaded, so the next page is fetched to look in
+    let effects = requests(typed(&mut state, "n"));
+    assert!(matches!(
+        effects.as_slice(),
+        [Effect::FetchMessages { limit, .. }] if *limit == 2 * PAGE_SIZE
+    ));
+    state.update(messages([1; 32], 2, conversation(60)));
+    assert_eq!(current(&state), Some((54, 0)));
+    typed(&mut state, "n");
+    assert_eq!(
+        state.toast.as_ref().unwrap().0,
+        "no older messages contain `needle`"
+    );
+    // arrivals leave the current match where it was
+    state.update(messages([1; 32], 3, conversation(61)));
+    assert_eq!(current(&state), Some((55, 0)));
+    typed(&mut state, "N");
+    assert_eq!(current(&state), Some((35, 0)));
+    assert_eq!(state.searches, ["needle"]);
+    state.update(key('\x1b'));
+    assert!(state.search.is_none());
+}
+
+#[test]
+fn damaged_records_do_not_end_the_history() {
+    let mut state = state();
+    state.update(Event::Tick);
+    state.update(users(vec![user(1, "alice")]));
+    let msg = |i: i64| Message {
+        inbound: true,
+        time: i * 60,
+        content: Text::Utf8(format!("hi {}", i)),
+    };
+    // a full page, one of which could not be decoded
+    state.update(Event::Messages(
+        [1; 32],
+        1,
+        Ok(Decoded {
+            records: (1..PAGE_SIZE as i64).rev().map(msg).collect(),
+            warnings: Vec::new(),
+            received: PAGE_SIZE,
+        }),
+    ));
+    assert!(!state.complete);
+    // while a short one is the start of the conversation
+    state.update(messages([1; 32], 2, (1..10).rev().map(msg).collect()));
+    assert!(state.complete);
+}
+
+#[test]
+fn relative_timestamps_are_kept_current() {
+    let mut state = state();
+    state.time_format.relative = true;
+    state.update(Event::Tick);
+    state.update(users(vec![user(1, "alice")]));
+    let hi = Message {
+        inbound: true,
+        time: now().timestamp(),
+        content: Text::Utf8("hi".to_owned()),
+    };
+    state.update(messages([1; 32], 1, vec![hi]));
+    let stamps = |effects: Vec<Effect>| -> Vec<String> {
+        effects
+            .into_iter()
+            .filter_map(|e| match e {
+                Effect::Render(Render::Feed { lines, .. }) => Some(lines),
+                _ => None,
+            })
+            .flatten()
+            .filter(|l| matches!(l.role, Role::Timestamp))
+            .map(|l| l.text)
+            .collect()
+    };
+    advance(60 * 2);
+    assert_eq!(stamps(state.update(Event::Tick)), ["2m ago"]);
+    advance(60 * 60);
+    assert_eq!(stamps(state.update(Event::Tick)), ["1h ago"]);
+}
@@ -0,0 +1,135 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const ELLIPSIS: &str = "…";
+
+/// Columns `text` takes up on the terminal.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Columns a single grapheme takes up. Emoji sequences are drawn as one
+/// double width cell rather than one per code point.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    std::cmp::min(UnicodeWidthStr::width(grapheme), 2)
+}
+
+/// `text` with control characters replaced, since the terminal would act
+/// on them rather than draw them.
+pub fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { '\u{fffd}' } else { c })
+        .collect()
+}
+
+/// The longest prefix of `text` that fits in `width` columns, without
+/// splitting a grapheme.
+pub fn take_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// The longest suffix of `text` that fits in `width` columns.
+pub fn tail_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, grapheme) in text.grapheme_indices(true).rev() {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &text[i + grapheme.len()..];
+        }
+    }
+    text
+}
+
+/// The grapheme drawn starting at display column `col` of `text`, if any.
+pub fn grapheme_at(text: &str, col: usize) -> Option<&str> {
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        if used == col {
+            return Some(grapheme);
+        }
+        used += grapheme_width(grapheme);
+        if used > col {
+            return None;
+        }
+    }
+    None
+}
+
+/// `text` made printable and cut down to `width` columns, ending in an
+/// ellipsis if anything had to go.
+pub fn truncate(text: &str, width: usize) -> String {
+    let text = &printable(text);
+    if self::width(text) <= width {
+        return text.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    format!("{}{}", take_width(text, width - 1), ELLIPSIS)
+}
+
+/// `text` as `wrap` sees it before breaking it up: printable words one
+/// space apart, with paragraphs on lines of their own. The lines `wrap`
+/// returns are pieces of this, in order, split at a space, a line break,
+/// or inside a word that was too wide.
+pub fn normalize(text: &str) -> String {
+    let paras: Vec<String> = text
+        .split('\n')
+        .map(|para| {
+            let words: Vec<String> = para.split_whitespace().map(printable).collect();
+            words.join(" ")
+        })
+        .collect();
+    paras.join("\n")
+}
+
+/// Greedy word wrap to `width` columns. Words wider than that are split
+/// between graphemes, and explicit newlines in the text are kept.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = std::cmp::max(width, 2);
+    let mut lines = Vec::new();
+    for para in text.split('\n') {
+        let mut line = String::new();
+        let mut len = 0;
+        for word in para.split_whitespace() {
+            let word = printable(word);
+            let mut word = word.as_str();
+            let mut word_len = self::width(word);
+            if len > 0 && len + 1 + word_len > width {
+                lines.push(std::mem::take(&mut line));
+                len = 0;
+            }
+            while word_len > width {
+                if len > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    len = 0;
+                }
+                let head = take_width(word, width);
+                lines.push(head.to_owned());
+                word = &word[head.len()..];
+                word_len -= self::width(head);
+            }
+            if word_len == 0 {
+                continue;
+            }
+            if len > 0 {
+                line.push(' ');
+                len += 1;
+            }
+            line.push_str(word);
+            len += word_len;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests;
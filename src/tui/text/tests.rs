@@ -0,0 +1,96 @@
+use super::*;
+
+/// a family, three emoji joined by zero width joiners
+const FAMILY: &str = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+/// `e` and a combining acute accent
+const E_ACUTE: &str = "e\u{301}";
+
+#[test]
+fn widths_count_columns_per_grapheme() {
+    assert_eq!(width(""), 0);
+    assert_eq!(width("abc"), 3);
+    assert_eq!(width("日本語"), 6);
+    assert_eq!(width(E_ACUTE), 1);
+    assert_eq!(width(FAMILY), 2);
+    assert_eq!(width(&format!("a{}b", FAMILY)), 4);
+}
+
+#[test]
+fn prefixes_and_suffixes_keep_graphemes_whole() {
+    assert_eq!(take_width("日本語", 3), "日");
+    assert_eq!(take_width("日本語", 4), "日本");
+    assert_eq!(take_width(&format!("{}x", E_ACUTE), 1), E_ACUTE);
+    assert_eq!(take_width(&format!("a{}b", FAMILY), 2), "a");
+    assert_eq!(
+        take_width(&format!("a{}b", FAMILY), 3),
+        format!("a{}", FAMILY)
+    );
+    assert_eq!(tail_width("日本語", 3), "語");
+    assert_eq!(tail_width(&format!("a{}", FAMILY), 2), FAMILY);
+    assert_eq!(tail_width(&format!("x{}", E_ACUTE), 1), E_ACUTE);
+    // nothing fits in less than a wide glyph
+    assert_eq!(take_width("日本語", 1), "");
+    assert_eq!(tail_width("日本語", 1), "");
+    assert_eq!(take_width("abc", 0), "");
+    assert_eq!(tail_width("abc", 0), "");
+}
+
+#[test]
+fn graphemes_are_found_by_the_column_they_start_at() {
+    let text = format!("a日{}b", FAMILY);
+    assert_eq!(grapheme_at(&text, 0), Some("a"));
+    assert_eq!(grapheme_at(&text, 1), Some("日"));
+    // the second half of a wide glyph
+    assert_eq!(grapheme_at(&text, 2), None);
+    assert_eq!(grapheme_at(&text, 3), Some(FAMILY));
+    assert_eq!(grapheme_at(&text, 5), Some("b"));
+    assert_eq!(grapheme_at(&text, 6), None);
+    assert_eq!(grapheme_at("", 0), None);
+}
+
+#[test]
+fn truncation_ends_in_an_ellipsis_that_fits() {
+    assert_eq!(truncate("日本語", 6), "日本語");
+    assert_eq!(truncate("日本語", 5), "日本…");
+    assert_eq!(truncate("日本語", 4), "日…");
+    assert_eq!(truncate("日本語", 1), "…");
+    assert_eq!(truncate("日本語", 0), "");
+    assert_eq!(
+        truncate(&format!("{0}{0}", FAMILY), 3),
+        format!("{}…", FAMILY)
+    );
+    assert_eq!(
+        truncate(&format!("{0}{0}{0}", E_ACUTE), 2),
+        format!("{}…", E_ACUTE)
+    );
+    // control characters are drawn as one column each
+    assert_eq!(truncate("ab\x07", 3), "ab\u{fffd}");
+}
+
+#[test]
+fn wrapping_breaks_between_graphemes() {
+    assert_eq!(wrap("hello 世界 ok", 7), ["hello", "世界 ok"]);
+    assert_eq!(wrap("日本語です", 4), ["日本", "語で", "す"]);
+    // a wide glyph is never split, even where that leaves a column over
+    assert_eq!(wrap("日本語", 3), ["日", "本", "語"]);
+    assert_eq!(
+        wrap(&format!("{0}{0}", FAMILY), 3),
+        [FAMILY.to_owned(), FAMILY.to_owned()]
+    );
+    assert_eq!(
+        wrap(&format!("{0}{0}{0}", E_ACUTE), 2),
+        [format!("{0}{0}", E_ACUTE), E_ACUTE.to_owned()]
+    );
+    // narrower than a wide glyph is taken as wide enough for one
+    assert_eq!(wrap("日本", 1), ["日", "本"]);
+    assert_eq!(wrap("日本", 0), ["日", "本"]);
+    assert_eq!(wrap("a\n\nb", 5), ["a", "", "b"]);
+}
+
+#[test]
+fn normalizing_collapses_spaces_within_paragraphs() {
+    assert_eq!(normalize("  a \t b\n\nc\x07 "), "a b\n\nc\u{fffd}");
+    // the lines `wrap` makes are pieces of it
+    let text = "some  日本語\nwords";
+    assert_eq!(wrap(text, 6).join(" "), normalize(text).replace('\n', " "));
+}
@@ -0,0 +1,369 @@
+use failure::{bail, format_err, Error, ResultExt};
+use pancurses::{Attribute, Attributes, ColorPair};
+
+use super::backend::Borders;
+use super::config;
+
+/// The file in the configuration directory themes are read from.
+pub const FILE: &str = "theme";
+
+/// What a piece of the screen is, which decides how it looks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Border,
+    FocusedBorder,
+    /// titles of the dialog and the help, and its headings
+    Title,
+    /// the highlighted contact
+    Selection,
+    Cursor,
+    /// unread counts in the sidebar
+    Unread,
+    /// contacts in the sidebar that messages arrived for
+    Arrived,
+    /// the open contact's name in the namebar
+    Name,
+    /// the open contact's onion address
+    Address,
+    Inbound,
+    Outbound,
+    Timestamp,
+    /// the separator where the day changes in the feed
+    Day,
+    /// text that is there for context only
+    Muted,
+    Error,
+    /// what a command did, shown in the status bar for a while
+    Notice,
+    Connected,
+    Retrying,
+    Offline,
+    /// the text fields of the dialog
+    Field,
+    /// the line typed at the command prompt
+    Prompt,
+    /// text in the feed found by a search
+    Match,
+    /// the match the feed was last moved to
+    CurrentMatch,
+}
+
+const ROLES: [Role; 23] = [
+    Role::Border,
+    Role::FocusedBorder,
+    Role::Title,
+    Role::Selection,
+    Role::Cursor,
+    Role::Unread,
+    Role::Arrived,
+    Role::Name,
+    Role::Address,
+    Role::Inbound,
+    Role::Outbound,
+    Role::Timestamp,
+    Role::Day,
+    Role::Muted,
+    Role::Error,
+    Role::Notice,
+    Role::Connected,
+    Role::Retrying,
+    Role::Offline,
+    Role::Field,
+    Role::Prompt,
+    Role::Match,
+    Role::CurrentMatch,
+];
+
+impl Role {
+    /// The name used in theme files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Border => "border",
+            Role::FocusedBorder => "focused-border",
+            Role::Title => "title",
+            Role::Selection => "selection",
+            Role::Cursor => "cursor",
+            Role::Unread => "unread",
+            Role::Arrived => "arrived",
+            Role::Name => "name",
+            Role::Address => "address",
+            Role::Inbound => "inbound",
+            Role::Outbound => "outbound",
+            Role::Timestamp => "timestamp",
+            Role::Day => "day",
+            Role::Muted => "muted",
+            Role::Error => "error",
+            Role::Notice => "notice",
+            Role::Connected => "connected",
+            Role::Retrying => "retrying",
+            Role::Offline => "offline",
+            Role::Field => "field",
+            Role::Prompt => "prompt",
+            Role::Match => "match",
+            Role::CurrentMatch => "current-match",
+        }
+    }
+}
+
+const COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+const ATTRIBUTES: [(&str, Attribute); 6] = [
+    ("bold", Attribute::Bold),
+    ("dim", Attribute::Dim),
+    ("reverse", Attribute::Reverse),
+    ("underline", Attribute::Underline),
+    ("italic", Attribute::Italic),
+    ("blink", Attribute::Blink),
+];
+
+/// A color by name or by its number in the terminal's palette, either
+/// of the first eight with `bright-` before it. `default` is the
+/// terminal's own, which is `None`.
+fn parse_color(s: &str) -> Result<Option<i16>, Error> {
+    if s == "default" {
+        return Ok(None);
+    }
+    let (bright, name) = match s.strip_prefix("bright-") {
+        Some(name) => (8, name),
+        None => (0, s),
+    };
+    if let Some(i) = COLORS.iter().position(|&c| c == name) {
+        return Ok(Some(bright + i as i16));
+    }
+    match name.parse::<u8>() {
+        Ok(n) if bright == 0 => Ok(Some(n.into())),
+        // the bright versions of 0 to 7 are 8 to 15
+        Ok(n) if n < 8 => Ok(Some(bright + i16::from(n))),
+        Ok(n) => bail!("only colors 0 to 7 have a `bright-` version, not {}", n),
+        Err(_) => bail!("unknown color `{}`", s),
+    }
+}
+
+/// How one role looks: colors, which are `None` for the terminal's
+/// default, and attributes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<i16>,
+    pub bg: Option<i16>,
+    pub attrs: Vec<Attribute>,
+}
+impl Style {
+    fn new(fg: Option<i16>, attrs: &[Attribute]) -> Self {
+        Style {
+            fg,
+            bg: None,
+            attrs: attrs.to_vec(),
+        }
+    }
+    /// Parses words like `bold yellow on blue`: attributes, a foreground
+    /// color, and a background color after `on`. `plain`, or nothing, is
+    /// the terminal's default look.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut style = Style::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            if word == "plain" {
+                continue;
+            }
+            if word == "on" {
+                let bg = words
+                    .next()
+                    .ok_or_else(|| format_err!("expected a color after `on`"))?;
+                style.bg = parse_color(bg)?;
+                continue;
+            }
+            if let Some((_, attr)) = ATTRIBUTES.iter().find(|(name, _)| *name == word) {
+                style.attrs.push(*attr);
+                continue;
+            }
+            style.fg = match parse_color(word) {
+                Ok(fg) => fg,
+                // only a color starts with `bright-`
+                Err(e) if word.starts_with("bright-") => return Err(e),
+                Err(_) => bail!("unknown color or attribute `{}`", word),
+            };
+        }
+        Ok(style)
+    }
+}
+
+/// How each role looks, and what borders are drawn with.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    styles: Vec<Style>,
+    pub borders: Borders,
+    /// off on monochrome terminals and with `NO_COLOR`, leaving only the
+    /// attributes
+    colors: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::colored()
+    }
+}
+
+impl Theme {
+    fn new(style: impl Fn(Role) -> Style) -> Self {
+        Theme {
+            styles: ROLES.iter().map(|&role| style(role)).collect(),
+            borders: Borders::Ascii,
+            colors: true,
+        }
+    }
+    /// The default theme: the monochrome one, with colors added.
+    pub fn colored() -> Self {
+        use Attribute::*;
+        let color = |name| COLORS.iter().position(|&c| c == name).map(|i| i as i16);
+        let mono = Theme::mono();
+        Theme::new(|role| match role {
+            Role::FocusedBorder => Style::new(color("cyan"), &[Bold]),
+            Role::Unread => Style::new(color("yellow"), &[Bold]),
+            Role::Arrived => Style::new(color("yellow"), &[Bold]),
+            Role::Outbound => Style::new(color("green"), &[]),
+            Role::Timestamp => Style::new(color("blue"), &[Dim]),
+            Role::Day => Style::new(color("magenta"), &[Bold]),
+            Role::Error => Style::new(color("red"), &[Bold]),
+            Role::Notice => Style::new(color("green"), &[Bold]),
+            Role::Connected => Style::new(color("green"), &[]),
+            Role::Retrying => Style::new(color("yellow"), &[Bold]),
+            Role::Offline => Style::new(color("red"), &[Reverse]),
+            Role::Match => Style {
+                bg: color("yellow"),
+                ..Style::new(color("black"), &[])
+            },
+            role => mono.style_of(role).clone(),
+        })
+    }
+    /// Attributes only, for terminals without colors.
+    pub fn mono() -> Self {
+        use Attribute::*;
+        Theme::new(|role| match role {
+            Role::Border | Role::Inbound | Role::Outbound | Role::Connected | Role::Prompt => {
+                Style::default()
+            }
+            Role::FocusedBorder | Role::Title | Role::Unread | Role::Arrived | Role::Name => {
+                Style::new(None, &[Bold])
+            }
+            Role::Day | Role::Error | Role::Notice | Role::Retrying => Style::new(None, &[Bold]),
+            Role::Selection | Role::Cursor | Role::Offline | Role::CurrentMatch => {
+                Style::new(None, &[Reverse])
+            }
+            Role::Address | Role::Timestamp | Role::Muted => Style::new(None, &[Dim]),
+            Role::Field | Role::Match => Style::new(None, &[Underline]),
+        })
+    }
+    pub fn style_of(&self, role: Role) -> &Style {
+        &self.styles[role as usize]
+    }
+    /// What to draw `role` with. Each role with colors has its own color
+    /// pair, numbered from 1 in the order of `ROLES`.
+    pub fn style(&self, role: Role) -> Attributes {
+        let style = self.style_of(role);
+        let mut attrs = style
+            .attrs
+            .iter()
+            .fold(Attributes::new(), |attrs, &attr| attrs | attr);
+        if self.colors && (style.fg.is_some() || style.bg.is_some()) {
+            attrs.set_color_pair(ColorPair(role as u8 + 1));
+        }
+        attrs
+    }
+    /// The color pairs `style` uses, as the pair, foreground and background,
+    /// with -1 for the terminal's default.
+    pub fn pairs(&self) -> Vec<(i16, i16, i16)> {
+        if !self.colors {
+            return Vec::new();
+        }
+        ROLES
+            .iter()
+            .map(|&role| (role, self.style_of(role)))
+            .filter(|(_, style)| style.fg.is_some() || style.bg.is_some())
+            .map(|(role, style)| {
+                let pair = role as i16 + 1;
+                (pair, style.fg.unwrap_or(-1), style.bg.unwrap_or(-1))
+            })
+            .collect()
+    }
+    /// Drops the colors, keeping the attributes.
+    pub fn monochrome(self) -> Self {
+        Theme {
+            colors: false,
+            ..self
+        }
+    }
+
+    /// Applies a theme file on top of this theme. The file can start from a
+    /// built in theme with `base = colored` or `base = mono`, pick
+    /// `borders = unicode` or `ascii`, and then restyle roles, one
+    /// `role = style` per line.
+    pub fn parse(mut self, text: &str) -> Result<Self, Error> {
+        let mut styled = false;
+        for (i, line) in text.lines().enumerate() {
+            let mut parse_line = |line: &str| -> Result<(), Error> {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    return Ok(());
+                }
+                let (name, value) = match line.find('=') {
+                    Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                    None => bail!("expected `role = style`"),
+                };
+                match name {
+                    "base" => {
+                        if styled {
+                            bail!("the base must come before any styles");
+                        }
+                        self.styles = match value {
+                            "colored" => Theme::colored().styles,
+                            "mono" => Theme::mono().styles,
+                            base => bail!("unknown base theme `{}`", base),
+                        };
+                    }
+                    "borders" => {
+                        self.borders = match value {
+                            "unicode" => Borders::Unicode,
+                            "ascii" => Borders::Ascii,
+                            borders => bail!("unknown borders `{}`", borders),
+                        };
+                    }
+                    name => {
+                        let role = *ROLES
+                            .iter()
+                            .find(|r| r.name() == name)
+                            .ok_or_else(|| format_err!("unknown role `{}`", name))?;
+                        self.styles[role as usize] = Style::parse(value)?;
+                        styled = true;
+                    }
+                }
+                Ok(())
+            };
+            parse_line(line).with_context(|_| format!("line {}", i + 1))?;
+        }
+        Ok(self)
+    }
+    /// The theme from the configuration directory, or the default if there
+    /// is none. Borders are line drawing characters where the locale is
+    /// UTF-8, and colors are left out if `NO_COLOR` is set.
+    pub fn load() -> Result<Self, Error> {
+        let mut theme = Theme {
+            borders: Borders::from_locale(),
+            ..Theme::default()
+        };
+        if let Some((path, text)) = config::read(FILE)? {
+            theme = theme
+                .parse(&text)
+                .with_context(|_| format!("in {}", path.display()))?;
+        }
+        // https://no-color.org: set to anything but the empty string
+        if std::env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()) {
+            theme = theme.monochrome();
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,109 @@
+use super::*;
+
+#[test]
+fn styles_parse_from_words() {
+    assert_eq!(
+        Style::parse("bold yellow on blue").unwrap(),
+        Style {
+            fg: Some(3),
+            bg: Some(4),
+            attrs: vec![Attribute::Bold],
+        }
+    );
+    assert_eq!(Style::parse("bright-red").unwrap().fg, Some(9));
+    assert_eq!(
+        Style::parse("208 on default").unwrap(),
+        Style::new(Some(208), &[])
+    );
+    assert_eq!(Style::parse("plain").unwrap(), Style::default());
+    assert_eq!(Style::parse("").unwrap(), Style::default());
+    assert!(Style::parse("blod").is_err());
+    assert!(Style::parse("red on").is_err());
+    assert!(Style::parse("256").is_err());
+}
+
+#[test]
+fn bright_colors_are_the_second_eight() {
+    for (s, color) in &[("bright-black", 8), ("bright-white", 15), ("bright-3", 11)] {
+        assert_eq!(parse_color(s).unwrap(), Some(*color), "{}", s);
+    }
+    assert_eq!(
+        Style::parse("red on bright-1").unwrap(),
+        Style {
+            fg: Some(1),
+            bg: Some(9),
+            attrs: Vec::new(),
+        }
+    );
+    let err = |s| Style::parse(s).unwrap_err().to_string();
+    assert_eq!(
+        err("bright-200"),
+        "only colors 0 to 7 have a `bright-` version, not 200"
+    );
+    assert_eq!(
+        err("on bright-8"),
+        "only colors 0 to 7 have a `bright-` version, not 8"
+    );
+    assert_eq!(err("bright-rde"), "unknown color `bright-rde`");
+    assert!(parse_color("bright-default").is_err());
+    assert!(parse_color("bright-").is_err());
+}
+
+#[test]
+fn files_restyle_on_top_of_a_base() {
+    let theme = Theme::default()
+        .parse(
+            "# mine\n\
+             base = mono\n\
+             borders = unicode\n\
+             \n\
+             outbound = cyan\n\
+             unread = black on yellow\n",
+        )
+        .unwrap();
+    assert_eq!(theme.borders, Borders::Unicode);
+    assert_eq!(theme.style_of(Role::Outbound), &Style::new(Some(6), &[]));
+    // everything else comes from the base
+    assert_eq!(theme.style_of(Role::Day), Theme::mono().style_of(Role::Day));
+    assert_eq!(
+        theme.pairs(),
+        vec![
+            (Role::Unread as i16 + 1, 0, 3),
+            (Role::Outbound as i16 + 1, 6, -1)
+        ]
+    );
+}
+
+#[test]
+fn monochrome_keeps_attributes_without_colors() {
+    let theme = Theme::default();
+    assert_eq!(
+        theme.style(Role::Error).color_pair().0,
+        Role::Error as u8 + 1
+    );
+    let theme = theme.monochrome();
+    assert!(theme.pairs().is_empty());
+    let error = theme.style(Role::Error);
+    assert!(error.is_bold());
+    assert_eq!(error.color_pair().0, 0);
+    // plain roles use no pair at all
+    assert_eq!(Theme::default().style(Role::Inbound), Attributes::new());
+}
+
+#[test]
+fn mistakes_in_files_are_reported_by_line() {
+    let err = |text| Theme::default().parse(text).unwrap_err();
+    assert_eq!(err("\nunread = yelow").to_string(), "line 2");
+    assert_eq!(
+        err("\nunread = yelow")
+            .iter_chain()
+            .last()
+            .unwrap()
+            .to_string(),
+        "unknown color or attribute `yelow`"
+    );
+    assert!(Theme::default().parse("unread").is_err());
+    assert!(Theme::default().parse("badge = red").is_err());
+    assert!(Theme::default().parse("borders = rounded").is_err());
+    assert!(Theme::default().parse("title = red\nbase = mono").is_err());
+}
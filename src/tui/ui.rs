@@ -0,0 +1,871 @@
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+
+use pancurses::*;
+use tokio::sync::mpsc;
+
+use cupslib::UserData;
+
+use super::backend::{Backend, Pane};
+use super::command::Prompt;
+use super::composer::Composer;
+use super::dialog::{self, Dialog};
+use super::keymap::HelpSection;
+use super::state::{
+    display_name, feed_size, Align, Connection, Event, FeedLine, Layout, MouseEvent, Rect,
+    Selected, MIN_SIZE,
+};
+use super::text;
+use super::theme::{Role, Theme};
+
+/// How the UI thread waits between handling keys and drawing. On unix it
+/// sleeps until a key comes in or a render command is sent. Elsewhere
+/// `getch` waits a short while for a key, after which the thread looks for
+/// render commands whether any were sent or not.
+mod wake;
+
+/// Drawing to be done by the UI thread. Each command redraws one part of
+/// the screen from the data it carries.
+pub enum Render {
+    /// blanks the screen, so it is drawn again from scratch
+    Clear,
+    Borders(Selected),
+    Sidebar {
+        users: Vec<UserData>,
+        top: usize,
+        selected: usize,
+        open: Option<[u8; 32]>,
+        /// contacts with messages that arrived while the TUI was running
+        arrived: Vec<[u8; 32]>,
+        filter: Option<(String, bool)>,
+    },
+    /// the open contact, and what keys do while the namebar has focus
+    Namebar(Option<UserData>, Option<String>),
+    Feed {
+        lines: Vec<FeedLine>,
+        scroll: usize,
+        focused: bool,
+        loading: bool,
+        /// the search and how many it found, while there is one
+        search: Option<String>,
+    },
+    Input {
+        composer: Composer,
+        focused: bool,
+        /// the composer's mode, with a modal keymap
+        mode: Option<&'static str>,
+    },
+    Status {
+        connection: Connection,
+        /// when a fetch last succeeded, formatted
+        synced: Option<String>,
+        /// an error or notice to show for a while
+        toast: Option<(String, Role)>,
+        /// the command prompt, which takes the place of everything else
+        prompt: Option<Prompt>,
+    },
+    /// the dialog drawn over everything else, if any
+    Dialog(Option<Dialog>),
+    /// a titled box listed over everything else, such as the key bindings
+    Overlay(Option<(String, Vec<HelpSection>)>),
+    /// rings the terminal bell
+    Bell,
+    /// sets the terminal title, which is put back when the UI thread ends
+    Title(String),
+    /// hands the terminal to the user's editor on a draft, which is
+    /// answered with `Event::Edited`
+    Edit(String),
+}
+
+/// The panes, laid out for the current screen size.
+struct Panes<P> {
+    layout: Layout,
+    sidebar: P,
+    topbar: P,
+    feed: P,
+    input: P,
+    status: P,
+}
+impl<P: Pane> Panes<P> {
+    /// Splits the screen into panes, or `None` if it is smaller than
+    /// `MIN_SIZE`.
+    fn new<B: Backend<Pane = P>>(backend: &B) -> Option<Self> {
+        let (rows, cols) = backend.size();
+        let layout = Layout::new(rows, cols)?;
+        Some(Panes {
+            layout,
+            sidebar: backend.pane(layout.sidebar)?,
+            topbar: backend.pane(layout.topbar)?,
+            feed: backend.pane(layout.feed)?,
+            input: backend.pane(layout.input)?,
+            status: backend.pane(layout.status)?,
+        })
+    }
+}
+
+/// Carries out render commands on a backend.
+pub struct Screen<B: Backend> {
+    backend: B,
+    panes: Option<Panes<B::Pane>>,
+    theme: Theme,
+    dialog: Option<Dialog>,
+    overlay: Option<(String, Vec<HelpSection>)>,
+    /// set once the terminal title has been changed
+    titled: bool,
+    /// answers to render commands, for the state
+    events: Vec<Event>,
+}
+impl<B: Backend> Screen<B> {
+    pub fn new(backend: B, theme: Theme) -> Self {
+        Screen {
+            panes: Panes::new(&backend),
+            backend,
+            theme,
+            dialog: None,
+            overlay: None,
+            titled: false,
+            events: Vec::new(),
+        }
+    }
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+    /// Puts back the terminal title, if it was changed.
+    pub fn restore_title(&mut self) {
+        if self.titled {
+            self.backend.restore_title();
+            self.titled = false;
+        }
+    }
+    /// Lays the panes out again after the backend changed size.
+    pub fn resize(&mut self) {
+        self.panes = None;
+        self.panes = Panes::new(&self.backend);
+    }
+    /// Applies a batch of render commands, then shows the result. Returns
+    /// what the state is to be told of in answer, such as edited text.
+    pub fn render_all<I: IntoIterator<Item = Render>>(&mut self, renders: I) -> Vec<Event> {
+        for render in renders {
+            self.render(render);
+        }
+        if self.panes.is_some() {
+            if let Some(dialog) = &self.dialog {
+                dialog.render(&self.backend, &self.theme);
+            }
+            if let Some((title, sections)) = &self.overlay {
+                render_overlay(&self.backend, &self.theme, title, sections);
+            }
+        }
+        self.backend.flush();
+        std::mem::take(&mut self.events)
+    }
+    fn render(&mut self, render: Render) {
+        let render = match render {
+            Render::Dialog(dialog) => return self.dialog = dialog,
+            Render::Overlay(overlay) => return self.overlay = overlay,
+            Render::Bell => return self.backend.bell(),
+            Render::Edit(text) => {
+                let edited = self.backend.edit(&text);
+                return self.events.push(Event::Edited(edited));
+            }
+            Render::Title(title) => {
+                if !self.titled {
+                    self.backend.save_title();
+                    self.titled = true;
+                }
+                return self.backend.set_title(&title);
+            }
+            render => render,
+        };
+        let theme = &self.theme;
+        let win = match &self.panes {
+            Some(win) => win,
+            None => {
+                if let Render::Clear = render {
+                    self.backend.clear();
+                    render_too_small(&self.backend);
+                }
+                return;
+            }
+        };
+        match render {
+            Render::Clear => self.backend.clear(),
+            Render::Borders(focus) => draw_borders(win, theme, focus),
+            Render::Sidebar {
+                users,
+                top,
+                selected,
+                open,
+                arrived,
+                filter,
+            } => {
+                let users: Vec<(&UserData, bool)> = users
+                    .iter()
+                    .map(|user| (user, arrived.contains(&user.id)))
+                    .collect();
+                let filter = filter.as_ref().map(|(f, typing)| (f.as_str(), *typing));
+                render_sidebar(win, theme, &users, top, selected, open, filter);
+            }
+            Render::Namebar(user, hint) => {
+                render_namebar(win, theme, user.as_ref(), hint.as_deref())
+            }
+            Render::Feed {
+                lines,
+                scroll,
+                focused,
+                loading,
+                search,
+            } => render_feed(
+                win,
+                theme,
+                &lines,
+                scroll,
+                focused,
+                loading,
+                search.as_deref(),
+            ),
+            Render::Input {
+                composer,
+                focused,
+                mode,
+            } => render_input(win, theme, &composer, focused, mode),
+            Render::Status {
+                prompt: Some(prompt),
+                ..
+            } => render_prompt(win, theme, &prompt),
+            Render::Status {
+                connection,
+                synced,
+                toast,
+                prompt: None,
+            } => {
+                let toast = toast.as_ref().map(|(text, role)| (text.as_str(), *role));
+                render_status(win, theme, connection, synced.as_deref(), toast)
+            }
+            Render::Dialog(_)
+            | Render::Overlay(_)
+            | Render::Bell
+            | Render::Title(_)
+            | Render::Edit(_) => {
+                unreachable!()
+            }
+        }
+    }
+}
+
+/// The sending half of the render channel. The UI thread may be asleep
+/// until a key is pressed, so each send also wakes it.
+pub struct Renderer {
+    send: Sender<Render>,
+    wake: wake::Wake,
+}
+impl Renderer {
+    /// Does nothing once the UI thread has gone.
+    pub fn send(&self, render: Render) {
+        if self.send.send(render).is_ok() {
+            self.wake.wake();
+        }
+    }
+}
+
+/// Starts the thread that owns the terminal. Every curses call is made on
+/// it: it reads keys and sends them to `events`, and draws whatever is sent
+/// on the returned channel. Dropping the sender ends the thread, which
+/// restores the terminal on the way out.
+pub fn spawn(
+    events: mpsc::UnboundedSender<Event>,
+    theme: Theme,
+) -> std::io::Result<(Renderer, JoinHandle<()>)> {
+    let (send, recv) = std::sync::mpsc::channel();
+    let (wake, woken) = wake::pair()?;
+    let handle = std::thread::spawn(move || {
+        let main = initscr();
+        init(&main);
+        let theme = init_colors(theme);
+        let mut screen = Screen::new(main, theme);
+        run(&mut screen, &events, &recv, woken);
+        screen.restore_title();
+        endwin();
+    });
+    Ok((Renderer { send, wake }, handle))
+}
+
+fn run(
+    screen: &mut Screen<Window>,
+    events: &mpsc::UnboundedSender<Event>,
+    renders: &Receiver<Render>,
+    mut woken: wake::Woken,
+) {
+    let (rows, cols) = screen.backend().size();
+    if events.send(Event::Resize(rows, cols)).is_err() {
+        return;
+    }
+    loop {
+        // curses may have read more than one key off the terminal, so it is
+        // asked until it has none left
+        while let Some(input) = screen.backend().getch() {
+            let event = match input {
+                Input::KeyResize => {
+                    screen.panes = None;
+                    // curses reads the terminal size again when it comes
+                    // back from endwin
+                    endwin();
+                    screen.backend().refresh();
+                    screen.resize();
+                    let (rows, cols) = screen.backend().size();
+                    Some(Event::Resize(rows, cols))
+                }
+                Input::KeyMouse => getmouse().ok().map(|event| {
+                    Event::Mouse(MouseEvent {
+                        y: event.y,
+                        x: event.x,
+                        bstate: event.bstate,
+                    })
+                }),
+                key => Some(Event::Key(key)),
+            };
+            if let Some(event) = event {
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+        let mut batch = Vec::new();
+        loop {
+            match renders.try_recv() {
+                Ok(render) => batch.push(render),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if !batch.is_empty() {
+            for event in screen.render_all(batch) {
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+        woken.wait();
+    }
+}
+
+fn render_too_small<B: Backend>(backend: &B) {
+    let (rows, cols) = backend.size();
+    let pane = match backend.pane(Rect {
+        y: 0,
+        x: 0,
+        rows,
+        cols,
+    }) {
+        Some(pane) => pane,
+        None => return,
+    };
+    let lines = [
+        "terminal too small".to_owned(),
+        format!("need at least {}x{}", MIN_SIZE.1, MIN_SIZE.0),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        let x = std::cmp::max(cols - line.len() as i32, 0) / 2;
+        pane.mvaddstr(rows / 2 - 1 + i as i32, x, line);
+    }
+    pane.noutrefresh();
+}
+
+fn init(main: &Window) {
+    resize_term(0, 0);
+    curs_set(0);
+    noecho();
+    #[cfg(unix)]
+    pass_flow_control_keys();
+    main.keypad(true);
+    wake::init(main);
+    mouseinterval(0);
+    mousemask(
+        BUTTON1_PRESSED | BUTTON1_CLICKED | BUTTON4_PRESSED | BUTTON5_PRESSED,
+        std::ptr::null_mut(),
+    );
+}
+
+/// Turns off XON/XOFF flow control, so C-s and C-q come in as keys rather
+/// than pausing the terminal. Curses puts the terminal back as it was on
+/// `endwin`, and back to this when it resumes.
+#[cfg(unix)]
+fn pass_flow_control_keys() {
+    unsafe {
+        let mut tio: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut tio) == 0 {
+            tio.c_iflag &= !libc::IXON;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &tio);
+        }
+    }
+    def_prog_mode();
+}
+
+/// Sets up the theme's color pairs, or leaves the colors out of the theme
+/// if the terminal has none.
+fn init_colors(theme: Theme) -> Theme {
+    if !has_colors() {
+        return theme.monochrome();
+    }
+    start_color();
+    // without default colors, -1 can't stand for them
+    let (fg, bg) = if use_default_colors() == ERR {
+        (COLOR_WHITE, COLOR_BLACK)
+    } else {
+        (-1, -1)
+    };
+    let color = |c: i16, default: i16| {
+        if c < 0 || i32::from(c) >= COLORS() {
+            default
+        } else {
+            c
+        }
+    };
+    for (pair, f, b) in theme.pairs() {
+        init_pair(pair, color(f, fg), color(b, bg));
+    }
+    theme
+}
+
+fn draw_borders<P: Pane>(win: &Panes<P>, theme: &Theme, focus: Selected) {
+    use Selected::*;
+    for &sel in [Sidebar, Namebar, Feed, Input].iter() {
+        let pane = match sel {
+            Sidebar => &win.sidebar,
+            Namebar => &win.topbar,
+            Feed => &win.feed,
+            Input => &win.input,
+        };
+        let style = theme.style(if sel == focus {
+            Role::FocusedBorder
+        } else {
+            Role::Border
+        });
+        pane.attron(style);
+        pane.draw_border(theme.borders);
+        pane.attroff(style);
+        pane.noutrefresh();
+    }
+}
+
+/// Draws one contact per row starting from `top`, with the filter on the
+/// last row while there is one. Each contact comes with whether messages
+/// arrived for it, which sets it apart. The open conversation is marked,
+/// the highlighted contact picked out and unread counts set against the
+/// right edge.
+fn render_sidebar<P: Pane>(
+    win: &Panes<P>,
+    theme: &Theme,
+    data: &[(&UserData, bool)],
+    top: usize,
+    selected: usize,
+    open: Option<[u8; 32]>,
+    filter: Option<(&str, bool)>,
+) {
+    let (height, width) = win.sidebar.get_max_yx();
+    let rows = std::cmp::max(height - 2 - filter.is_some() as i32, 0) as usize;
+    let cols = std::cmp::max(width - 2, 0) as usize;
+    if let Some((filter, typing)) = filter {
+        let y = height - 2;
+        win.sidebar.mv(y, 1);
+        win.sidebar.addstr(" ".repeat(cols));
+        let shown = text::tail_width(filter, cols.saturating_sub(3));
+        win.sidebar
+            .mvaddstr(y, 1, format!("/{}", text::printable(shown)));
+        if typing {
+            win.sidebar.attron(theme.style(Role::Cursor));
+            win.sidebar.addstr(" ");
+            win.sidebar.attroff(theme.style(Role::Cursor));
+        }
+    }
+    for row in 0..rows {
+        let y = row as i32 + 1;
+        win.sidebar.mv(y, 1);
+        win.sidebar.addstr(" ".repeat(cols));
+        let (i, &(user, arrived)) = match data.iter().enumerate().nth(top + row) {
+            Some(user) => user,
+            None => continue,
+        };
+        if i == selected {
+            win.sidebar.attron(theme.style(Role::Selection));
+            win.sidebar.mv(y, 1);
+            win.sidebar.addstr(" ".repeat(cols));
+        }
+        let unreads = if user.unreads > 0 {
+            format!(" {}", user.unreads)
+        } else {
+            String::new()
+        };
+        let name = display_name(user);
+        let marker = if open == Some(user.id) { ">" } else { " " };
+        let room = cols.saturating_sub(unreads.len() + 2);
+        win.sidebar.mvaddstr(y, 1, marker);
+        let style = theme.style(Role::Arrived);
+        if arrived {
+            win.sidebar.attron(style);
+        }
+        win.sidebar.addstr(text::truncate(&name, room));
+        if arrived {
+            win.sidebar.attroff(style);
+        }
+        if !unreads.is_empty() {
+            win.sidebar.attron(theme.style(Role::Unread));
+            win.sidebar
+                .mvaddstr(y, (cols - unreads.len()) as i32, &unreads);
+            win.sidebar.attroff(theme.style(Role::Unread));
+        }
+        if i == selected {
+            win.sidebar.attroff(theme.style(Role::Selection));
+        }
+    }
+    win.sidebar.noutrefresh();
+}
+
+/// Shows who the open conversation is with: the name over the full onion
+/// address, or both on one line if the bar is only one row high. While the
+/// bar has focus, `hint` sits in its border.
+fn render_namebar<P: Pane>(
+    win: &Panes<P>,
+    theme: &Theme,
+    user: Option<&UserData>,
+    hint: Option<&str>,
+) {
+    let (height, width) = win.topbar.get_max_yx();
+    let rows = std::cmp::max(height - 2, 0);
+    let cols = std::cmp::max(width - 4, 0) as usize;
+    for row in 0..rows {
+        win.topbar.mv(row + 1, 1);
+        win.topbar.addstr(" ".repeat(cols + 2));
+    }
+    match user {
+        Some(user) => {
+            let name = user.name.as_ref().map_or("", |name| name.as_str());
+            let onion = cupslib::pubkey_to_onion(&user.id).unwrap_or_default();
+            win.topbar.mv(1, 2);
+            win.topbar.attron(theme.style(Role::Name));
+            win.topbar.addstr(text::truncate(name, cols));
+            win.topbar.attroff(theme.style(Role::Name));
+            let (y, x) = if rows >= 2 {
+                (2, 0)
+            } else if name.is_empty() {
+                (1, 0)
+            } else {
+                (1, text::width(name) + 2)
+            };
+            if x < cols {
+                win.topbar.mv(y, x as i32 + 2);
+                win.topbar.attron(theme.style(Role::Address));
+                win.topbar.addstr(text::truncate(&onion, cols - x));
+                win.topbar.attroff(theme.style(Role::Address));
+            }
+        }
+        None => {
+            win.topbar.mv(1, 2);
+            win.topbar.attron(theme.style(Role::Muted));
+            win.topbar
+                .addstr(text::truncate("no conversation open", cols));
+            win.topbar.attroff(theme.style(Role::Muted));
+        }
+    }
+    let border = theme.style(if hint.is_some() {
+        Role::FocusedBorder
+    } else {
+        Role::Border
+    });
+    win.topbar.attron(border);
+    win.topbar.draw_border(theme.borders);
+    if let Some(hint) = hint {
+        let hint = format!(" {} ", hint);
+        let len = text::width(&hint) as i32;
+        if !hint.trim().is_empty() && len + 4 <= width {
+            win.topbar.mvaddstr(height - 1, width - 2 - len, &hint);
+        }
+    }
+    win.topbar.attroff(border);
+    win.topbar.noutrefresh();
+}
+
+/// Draws `lines` into the feed pane, scrolled back `scroll` lines from the
+/// last one, with search matches picked out. The border shows how far back
+/// the view is, whether an older page is being fetched, and any search.
+fn render_feed<P: Pane>(
+    win: &Panes<P>,
+    theme: &Theme,
+    lines: &[FeedLine],
+    scroll: usize,
+    focused: bool,
+    loading: bool,
+    search: Option<&str>,
+) {
+    let (rows, cols) = feed_size(&win.layout);
+    let (height, width) = win.feed.get_max_yx();
+    let border = theme.style(if focused {
+        Role::FocusedBorder
+    } else {
+        Role::Border
+    });
+    win.feed.attron(border);
+    win.feed.draw_border(theme.borders);
+    if loading {
+        win.feed.mvaddstr(0, width - 14, " loading... ");
+    }
+    if lines.len() > rows {
+        let end = lines.len() - scroll;
+        let indicator = if scroll == 0 {
+            " end ".to_owned()
+        } else {
+            format!(" {}% ", end * 100 / lines.len())
+        };
+        win.feed
+            .mvaddstr(height - 1, width - 2 - indicator.len() as i32, &indicator);
+    }
+    if let Some(search) = search {
+        let room = std::cmp::max(width - 16, 0) as usize;
+        let search = text::truncate(search, room.saturating_sub(2));
+        win.feed.mvaddstr(height - 1, 2, format!(" {} ", search));
+    }
+    win.feed.attroff(border);
+    let end = lines.len() - scroll;
+    let start = end.saturating_sub(rows);
+    for row in 0..rows {
+        win.feed.mv(row as i32 + 1, 1);
+        win.feed.addstr(" ".repeat(cols + 2));
+    }
+    for (row, line) in lines[start..end].iter().enumerate() {
+        let len = text::width(&line.text);
+        let x = match line.align {
+            Align::Left => 0,
+            Align::Center => cols.saturating_sub(len) / 2,
+            Align::Right => cols.saturating_sub(len),
+        };
+        win.feed.mv(row as i32 + 1, x as i32 + 2);
+        win.feed.attron(theme.style(line.role));
+        win.feed.addstr(text::truncate(&line.text, cols - x));
+        win.feed.attroff(theme.style(line.role));
+        for mark in &line.marks {
+            let at = x + text::width(&line.text[..mark.range.start]);
+            if at >= cols {
+                continue;
+            }
+            let role = if mark.current {
+                Role::CurrentMatch
+            } else {
+                Role::Match
+            };
+            win.feed.mv(row as i32 + 1, at as i32 + 2);
+            win.feed.attron(theme.style(role));
+            win.feed
+                .addstr(text::truncate(&line.text[mark.range.clone()], cols - at));
+            win.feed.attroff(theme.style(role));
+        }
+    }
+    win.feed.noutrefresh();
+}
+
+/// Redraws the composer, keeping the cursor row in view. The cursor is
+/// drawn as a highlighted cell, and only while the pane has focus, which is
+/// also when the mode shows in the border.
+fn render_input<P: Pane>(
+    win: &Panes<P>,
+    theme: &Theme,
+    composer: &Composer,
+    focused: bool,
+    mode: Option<&str>,
+) {
+    let (height, width) = win.input.get_max_yx();
+    if let (Some(mode), true) = (mode, focused) {
+        let mode = format!(" {} ", mode);
+        win.input.attron(theme.style(Role::FocusedBorder));
+        win.input.draw_border(theme.borders);
+        win.input
+            .mvaddstr(height - 1, width - 2 - mode.len() as i32, &mode);
+        win.input.attroff(theme.style(Role::FocusedBorder));
+    }
+    let rows = std::cmp::max(height - 2, 1) as usize;
+    let cols = std::cmp::max(width - 4, 1) as usize;
+    let (lines, (row, col)) = composer.layout(cols);
+    let top = (row + 1).saturating_sub(rows);
+    for i in 0..rows {
+        win.input.mv(i as i32 + 1, 1);
+        win.input.addstr(" ".repeat(cols + 2));
+        if let Some(line) = lines.get(top + i) {
+            win.input.mv(i as i32 + 1, 2);
+            win.input.addstr(line);
+        }
+    }
+    if focused {
+        let under = lines
+            .get(row)
+            .and_then(|l| text::grapheme_at(l, col))
+            .unwrap_or(" ");
+        win.input.mv((row - top) as i32 + 1, col as i32 + 2);
+        win.input.attron(theme.style(Role::Cursor));
+        win.input.addstr(under);
+        win.input.attroff(theme.style(Role::Cursor));
+    }
+    win.input.noutrefresh();
+}
+
+/// Draws the connection state and the last sync time on the left, and any
+/// error against the right edge. An error that does not fit beside the
+/// rest takes the whole row.
+fn render_status<P: Pane>(
+    win: &Panes<P>,
+    theme: &Theme,
+    connection: Connection,
+    synced: Option<&str>,
+    toast: Option<(&str, Role)>,
+) {
+    let (_, width) = win.status.get_max_yx();
+    let cols = std::cmp::max(width - 2, 0) as usize;
+    let (state, role) = match connection {
+        Connection::Connecting => ("connecting", Role::Muted),
+        Connection::Connected => ("connected", Role::Connected),
+        Connection::Retrying => ("retrying", Role::Retrying),
+        Connection::Offline => ("offline", Role::Offline),
+    };
+    let synced = match synced {
+        Some(time) => format!("  last synced {}", time),
+        None => "  not synced yet".to_owned(),
+    };
+    let left = text::width(state) + text::width(&synced);
+    win.status.mvaddstr(0, 0, " ".repeat(width as usize));
+    let room = cols.saturating_sub(left + 2);
+    let toast_only = match toast {
+        Some((toast, _)) => room < std::cmp::min(text::width(toast), 24),
+        None => false,
+    };
+    if !toast_only {
+        win.status.mv(0, 1);
+        win.status.attron(theme.style(role));
+        win.status.addstr(text::truncate(state, cols));
+        win.status.attroff(theme.style(role));
+        win.status.attron(theme.style(Role::Muted));
+        win.status.addstr(text::truncate(
+            &synced,
+            cols.saturating_sub(text::width(state)),
+        ));
+        win.status.attroff(theme.style(Role::Muted));
+    }
+    if let Some((toast, role)) = toast {
+        let toast = text::truncate(toast, if toast_only { cols } else { room });
+        let x = cols - text::width(&toast) + 1;
+        win.status.attron(theme.style(role));
+        win.status.mvaddstr(0, x as i32, toast);
+        win.status.attroff(theme.style(role));
+    }
+    win.status.noutrefresh();
+}
+
+/// Draws the command prompt across the status bar, followed by what Tab
+/// offered last, with the candidate in the line picked out.
+fn render_prompt<P: Pane>(win: &Panes<P>, theme: &Theme, prompt: &Prompt) {
+    let (_, width) = win.status.get_max_yx();
+    let cols = std::cmp::max(width - 2, 0) as usize;
+    win.status.mvaddstr(0, 0, " ".repeat(width as usize));
+    win.status
+        .mvaddstr(0, 1, if prompt.search { "/" } else { ":" });
+    let room = cols.saturating_sub(1);
+    let (candidates, shown) = prompt.candidates();
+    let field = if candidates.is_empty() {
+        room
+    } else {
+        std::cmp::min(text::width(prompt.line.text()) + 1, room)
+    };
+    dialog::render_field(
+        &win.status,
+        theme,
+        (0, 2),
+        field,
+        &prompt.line,
+        Role::Prompt,
+        true,
+    );
+    let mut left = room - field;
+    win.status.mv(0, 2 + field as i32);
+    for (i, candidate) in candidates.iter().enumerate() {
+        let candidate = text::printable(candidate);
+        if text::width(&candidate) + 2 > left {
+            break;
+        }
+        left -= text::width(&candidate) + 2;
+        win.status.addstr("  ");
+        let role = if shown == Some(i) {
+            Role::Selection
+        } else {
+            Role::Muted
+        };
+        win.status.attron(theme.style(role));
+        win.status.addstr(candidate);
+        win.status.attroff(theme.style(role));
+    }
+    win.status.noutrefresh();
+}
+
+/// Lists key bindings, or other pairs, under headings in a box over the
+/// panes, cut off with an ellipsis if they do not fit.
+fn render_overlay<B: Backend>(backend: &B, theme: &Theme, title: &str, sections: &[HelpSection]) {
+    let (rows, cols) = backend.size();
+    let mut lines = Vec::new();
+    for (title, bindings) in sections {
+        if bindings.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push((String::new(), None));
+        }
+        lines.push((title.clone(), Some(Role::Title)));
+        let keys = bindings.iter().map(|(k, _)| text::width(k)).max();
+        let keys = std::cmp::min(keys.unwrap_or(0), 20);
+        for (k, description) in bindings {
+            let k = text::truncate(k, keys);
+            let pad = " ".repeat(keys - text::width(&k));
+            lines.push((format!("  {}{}  {}", k, pad, description), None));
+        }
+    }
+    let longest = lines.iter().map(|(line, _)| text::width(line)).max();
+    let width = std::cmp::max(longest.unwrap_or(0) as i32 + 4, 60);
+    let width = std::cmp::min(cols - 4, width);
+    let height = std::cmp::min(lines.len() as i32 + 2, rows - 2);
+    let win = match backend.pane(Rect {
+        y: (rows - height) / 2,
+        x: (cols - width) / 2,
+        rows: height,
+        cols: width,
+    }) {
+        Some(win) => win,
+        None => return,
+    };
+    let inner = (width - 4) as usize;
+    win.erase();
+    win.attron(theme.style(Role::Border));
+    win.draw_border(theme.borders);
+    win.attroff(theme.style(Role::Border));
+    win.attron(theme.style(Role::Title));
+    win.mvaddstr(0, 2, format!(" {} ", title));
+    win.attroff(theme.style(Role::Title));
+    let close = " any key closes ";
+    if close.len() + 4 <= width as usize {
+        win.mvaddstr(height - 1, width - 2 - close.len() as i32, close);
+    }
+    let shown = (height - 2) as usize;
+    for (i, (line, role)) in lines.iter().take(shown).enumerate() {
+        let line = if i + 1 == shown && lines.len() > shown {
+            "…"
+        } else {
+            line
+        };
+        win.mv(i as i32 + 1, 2);
+        if let Some(role) = role {
+            win.attron(theme.style(*role));
+        }
+        win.addstr(text::truncate(line, inner));
+        if let Some(role) = role {
+            win.attroff(theme.style(*role));
+        }
+    }
+    win.noutrefresh();
+}
+
+#[cfg(test)]
+mod tests;
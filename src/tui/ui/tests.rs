@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+
+use insta::assert_snapshot;
+
+use chrono::{DateTime, TimeZone, Utc};
+use cupslib::{Decoded, Message, Text, TimeFormat, UserData, Zone};
+
+use super::*;
+use crate::tui::backend::memory::MemoryScreen;
+use crate::tui::keymap::Keymap;
+use crate::tui::state::{Effect, Notify, State};
+use crate::tui::theme::Theme;
+
+/// Mock contacts: alice with unread messages, a name that needs more than
+/// ascii, and a contact without a name.
+fn mock_users() -> Vec<UserData> {
+    let user = |n: u8, name: Option<&str>, unreads| UserData {
+        id: [n; 32],
+        name: name.map(|name| Text::Utf8(name.to_owned())),
+        unreads,
+    };
+    vec![
+        user(1, Some("alice"), 2),
+        user(2, Some("Zoë 🎉"), 0),
+        user(3, None, 1),
+    ]
+}
+
+/// A conversation with `n` messages, oldest first, an hour apart and
+/// alternating between inbound and outbound.
+fn mock_conversation(n: i64) -> Vec<Message> {
+    (0..n)
+        .map(|i| Message {
+            inbound: i % 2 == 0,
+            // 2020-03-01 00:00:00 UTC
+            time: 1_583_020_800 + i * 60 * 60,
+            content: Text::Utf8(format!(
+                "message {} {}",
+                i,
+                "lorem ipsum ".repeat(i as usize % 4)
+            )),
+        })
+        .collect()
+}
+
+/// 2020-03-01 12:00:00 UTC, so sync times show the same on every run.
+fn now() -> DateTime<Utc> {
+    Utc.timestamp_opt(1_583_064_000, 0).unwrap()
+}
+
+fn decoded<T>(records: Vec<T>) -> failure::Fallible<Decoded<T>> {
+    Ok(Decoded {
+        received: records.len(),
+        records,
+        warnings: Vec::new(),
+    })
+}
+
+/// Drives the state and a screen in memory, answering network requests
+/// from mock data as soon as they are made.
+struct Harness {
+    state: State,
+    screen: Screen<MemoryScreen>,
+    users: Vec<UserData>,
+    /// every conversation, oldest message first
+    conversations: Vec<([u8; 32], Vec<Message>)>,
+    /// set to make fetches fail
+    offline: bool,
+}
+impl Harness {
+    fn new(rows: i32, cols: i32) -> Self {
+        let users = mock_users();
+        let conversations = users
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (u.id, mock_conversation(4 + 3 * i as i64)))
+            .collect();
+        let mut harness = Harness {
+            state: State::new(
+                TimeFormat {
+                    zone: Zone::Utc,
+                    ..TimeFormat::default()
+                },
+                Keymap::default(),
+            ),
+            screen: Screen::new(MemoryScreen::new(rows, cols), Theme::default()),
+            users,
+            conversations,
+            offline: false,
+        };
+        harness.state.clock = now;
+        harness.dispatch(Event::Resize(rows, cols));
+        harness.dispatch(Event::Tick);
+        harness
+    }
+    /// Applies `event`, along with everything that follows from it.
+    fn dispatch(&mut self, event: Event) {
+        let mut events = VecDeque::new();
+        events.push_back(event);
+        while let Some(event) = events.pop_front() {
+            let mut renders = Vec::new();
+            for effect in self.state.update(event) {
+                match effect {
+                    Effect::Render(render) => renders.push(render),
+                    effect => events.extend(self.answer(effect)),
+                }
+            }
+            events.extend(self.screen.render_all(renders));
+        }
+    }
+    fn answer(&mut self, effect: Effect) -> Option<Event> {
+        let refused = || failure::err_msg("error trying to connect: Connection refused");
+        Some(match effect {
+            Effect::FetchUsers if self.offline => Event::Users(Err(refused())),
+            Effect::FetchMessages { id, fetch, .. } if self.offline => {
+                Event::Messages(id, fetch, Err(refused()))
+            }
+            Effect::FetchUsers => Event::Users(decoded(self.users.clone())),
+            Effect::FetchMessages { id, fetch, limit } => {
+                let messages = self.conversation(id);
+                let newest = messages.iter().rev().take(limit).cloned().collect();
+                Event::Messages(id, fetch, decoded(newest))
+            }
+            Effect::Send { seq, id, content } => {
+                let messages = self.conversation(id);
+                let time = messages.last().map_or(0, |m| m.time + 60);
+                messages.push(Message {
+                    inbound: false,
+                    time,
+                    content: Text::Utf8(content),
+                });
+                Event::Sent(seq, Ok(()))
+            }
+            Effect::Save { onion, name } => {
+                let id = cupslib::onion_to_pubkey(&onion).unwrap().0;
+                let name = Some(Text::Utf8(name));
+                match self.users.iter_mut().find(|u| u.id == id) {
+                    Some(user) => user.name = name,
+                    None => self.users.push(UserData {
+                        id,
+                        name,
+                        unreads: 0,
+                    }),
+                }
+                Event::Saved(Ok(()))
+            }
+            Effect::Export { id, path, .. } => {
+                Event::Exported(Ok((path, self.conversation(id).len())))
+            }
+            Effect::Render(_) | Effect::SaveDrafts(_) | Effect::Quit => return None,
+        })
+    }
+    fn conversation(&mut self, id: [u8; 32]) -> &mut Vec<Message> {
+        let i = match self.conversations.iter().position(|(c, _)| *c == id) {
+            Some(i) => i,
+            None => {
+                self.conversations.push((id, Vec::new()));
+                self.conversations.len() - 1
+            }
+        };
+        &mut self.conversations[i].1
+    }
+    fn press(&mut self, keys: &[Input]) -> &mut Self {
+        for &key in keys {
+            self.dispatch(Event::Key(key));
+        }
+        self
+    }
+    fn typed(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            self.dispatch(Event::Key(Input::Character(c)));
+        }
+        self
+    }
+    fn resize(&mut self, rows: i32, cols: i32) -> &mut Self {
+        self.screen.backend().resize(rows, cols);
+        self.screen.resize();
+        self.dispatch(Event::Resize(rows, cols));
+        self
+    }
+    fn snapshot(&self) -> String {
+        self.screen.backend().snapshot()
+    }
+}
+
+const ENTER: Input = Input::Character('\n');
+const ESC: Input = Input::Character('\x1b');
+const TAB: Input = Input::Character('\t');
+
+#[test]
+fn first_contact_opens_on_start() {
+    let h = Harness::new(24, 80);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn opening_a_contact_from_the_sidebar() {
+    let mut h = Harness::new(24, 80);
+    h.typed("j").press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn composing_over_several_lines() {
+    let mut h = Harness::new(24, 80);
+    h.press(&[TAB]).typed("first line");
+    h.press(&[ESC, ENTER])
+        .typed("second line, which goes on and on ")
+        .typed("for long enough that it has to wrap");
+    h.press(&[Input::KeyLeft, Input::KeyLeft]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn sent_messages_show_in_the_feed() {
+    let mut h = Harness::new(24, 80);
+    h.press(&[TAB]).typed("hello there").press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn scrolling_back_through_the_feed() {
+    let mut h = Harness::new(24, 80);
+    h.press(&[Input::KeyEnd, ENTER, TAB]);
+    h.press(&[Input::KeyPPage, Input::KeyUp]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn filtering_the_sidebar() {
+    let mut h = Harness::new(24, 80);
+    h.typed("/zo");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn adding_a_contact() {
+    let mut h = Harness::new(24, 80);
+    h.typed("a")
+        .typed("not an address")
+        .press(&[TAB])
+        .typed("dave");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn renaming_a_contact() {
+    let mut h = Harness::new(24, 80);
+    h.press(&[TAB, TAB, TAB]).typed("r");
+    h.press(&[Input::KeyBackspace; 5])
+        .typed("Alice B.")
+        .press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn narrow_terminals_truncate_text() {
+    let mut h = Harness::new(12, 48);
+    h.typed("j").press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn resizing_lays_the_panes_out_again() {
+    let mut h = Harness::new(24, 80);
+    h.resize(10, 40);
+    assert_snapshot!("resizing_too_small", h.snapshot());
+    h.resize(30, 100);
+    assert_snapshot!("resizing_larger", h.snapshot());
+}
+
+#[test]
+fn fetch_errors_show_in_the_status_bar() {
+    let mut h = Harness::new(24, 80);
+    h.offline = true;
+    h.dispatch(Event::Tick);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn help_lists_the_bindings_for_the_focused_pane() {
+    let mut h = Harness::new(24, 80);
+    h.typed("?");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn vim_mode_shows_in_the_composer() {
+    let mut h = Harness::new(24, 80);
+    h.state.keymap = Keymap::vim();
+    h.press(&[TAB]).typed("hello").press(&[ESC]).typed("hh");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn unicode_borders() {
+    let mut h = Harness::new(24, 80);
+    let theme = Theme::default().parse("borders = unicode").unwrap();
+    h.screen = Screen::new(MemoryScreen::new(24, 80), theme);
+    h.dispatch(Event::Resize(24, 80));
+    h.typed("?");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn arriving_messages_bump_and_highlight_the_contact() {
+    let mut h = Harness::new(24, 80);
+    h.state.notify = Notify {
+        bell: true,
+        title: true,
+    };
+    h.users[1].unreads = 3;
+    h.dispatch(Event::Tick);
+    assert_eq!(h.screen.backend().bells(), 1);
+    assert_eq!(
+        h.screen.backend().title().as_deref(),
+        Some("cups (6 unread)")
+    );
+    assert_snapshot!(h.snapshot());
+    h.screen.restore_title();
+    assert_eq!(h.screen.backend().title(), None);
+}
+
+#[test]
+fn command_prompt_offers_contact_names() {
+    let mut h = Harness::new(24, 80);
+    h.users.push(UserData {
+        id: [4; 32],
+        name: Some(Text::Utf8("Zoe".to_owned())),
+        unreads: 0,
+    });
+    h.dispatch(Event::Tick);
+    h.typed(":o").press(&[TAB]).typed("z").press(&[TAB, TAB]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn profiles_show_over_the_panes() {
+    let mut h = Harness::new(24, 80);
+    h.typed(":profile zo").press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn exports_report_in_the_status_bar() {
+    let mut h = Harness::new(24, 80);
+    h.typed(":export /tmp/alice.txt").press(&[ENTER]);
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn drafts_go_through_the_editor() {
+    let mut h = Harness::new(24, 80);
+    h.press(&[TAB]).typed("a long").press(&[ESC]).typed("e");
+    assert!(h.snapshot().contains("editing failed: no editor"));
+    h.screen
+        .backend()
+        .set_editor(|draft| format!("{} message,\nwritten at leisure\n\n", draft));
+    h.press(&[ESC]).typed("e");
+    assert_snapshot!(h.snapshot());
+}
+
+#[test]
+fn searching_highlights_matches_in_the_feed() {
+    let mut h = Harness::new(24, 80);
+    h.screen = Screen::new(MemoryScreen::new(24, 80), Theme::mono());
+    h.dispatch(Event::Resize(24, 80));
+    h.press(&[TAB, TAB]).typed("/ipsum LOR");
+    assert!(h.snapshot().contains("\n /ipsum LOR"));
+    h.press(&[ENTER]).typed("n");
+    assert_snapshot!(h.snapshot());
+}
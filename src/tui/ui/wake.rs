@@ -0,0 +1,84 @@
+#[cfg(unix)]
+pub use self::unix::*;
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    use pancurses::Window;
+
+    /// Milliseconds the UI thread sleeps at most before asking curses for a
+    /// resize. The signal telling of one is taken by whichever thread of the
+    /// process the kernel picks, and only cuts the sleep short if it lands on
+    /// this one.
+    const RESIZE_INTERVAL: i32 = 500;
+
+    /// Wakes the thread holding the matching `Woken`.
+    pub struct Wake(UnixStream);
+    impl Wake {
+        pub fn wake(&self) {
+            // if the pipe is full, the thread is already due to wake
+            let _ = (&self.0).write(&[0]);
+        }
+    }
+
+    pub struct Woken(UnixStream);
+    impl Woken {
+        /// Sleeps until a key comes in on the terminal, `Wake::wake` is
+        /// called, or it is time to look for a resize.
+        pub fn wait(&mut self) {
+            let mut fds = [libc::STDIN_FILENO, self.0.as_raw_fd()].map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            // the result is of no interest, as everything is looked at again
+            // after
+            unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, RESIZE_INTERVAL) };
+            while self.0.read(&mut [0; 64]).map_or(false, |n| n > 0) {}
+        }
+    }
+
+    pub fn pair() -> std::io::Result<(Wake, Woken)> {
+        let (wake, woken) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        woken.set_nonblocking(true)?;
+        Ok((Wake(wake), Woken(woken)))
+    }
+
+    /// Keys are waited for in `Woken::wait`, so `getch` only returns the
+    /// ones that have come.
+    pub fn init(main: &Window) {
+        main.nodelay(true);
+    }
+}
+
+#[cfg(not(unix))]
+mod poll {
+    use pancurses::Window;
+
+    /// Milliseconds `getch` waits for a key before the UI thread looks for
+    /// render commands again.
+    const POLL_INTERVAL: i32 = 20;
+
+    pub struct Wake;
+    impl Wake {
+        pub fn wake(&self) {}
+    }
+
+    pub struct Woken;
+    impl Woken {
+        /// `getch` has already waited.
+        pub fn wait(&mut self) {}
+    }
+
+    pub fn pair() -> std::io::Result<(Wake, Woken)> {
+        Ok((Wake, Woken))
+    }
+
+    pub fn init(main: &Window) {
+        main.timeout(POLL_INTERVAL);
+    }
+}
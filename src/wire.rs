@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use crate::{DecodeError, DecodeWarning, Text};
+
+struct Field {
+    record: usize,
+    offset: usize,
+    len: usize,
+    name: &'static str,
+}
+
+/// Cursor over a binary response that remembers where each field came from,
+/// so a response that fails to decode can be dumped with its record boundaries.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    records: usize,
+    fields: Vec<Field>,
+    warnings: Vec<DecodeWarning>,
+}
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader {
+            buf,
+            pos: 0,
+            records: 0,
+            fields: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+    pub fn begin_record(&mut self) {
+        self.records += 1;
+    }
+    /// How many records have been begun.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+    /// Notes a problem with the current record.
+    pub fn warn(&mut self, error: DecodeError) {
+        self.warnings.push(DecodeWarning {
+            record: self.records.saturating_sub(1),
+            error,
+        });
+    }
+    pub fn into_warnings(self) -> Vec<DecodeWarning> {
+        self.warnings
+    }
+    pub fn take(&mut self, name: &'static str, len: usize) -> Result<&'a [u8], DecodeError> {
+        let remaining = self.buf.len() - self.pos;
+        if len > remaining {
+            return Err(DecodeError::UnexpectedEof {
+                field: name,
+                offset: self.pos,
+                needed: len,
+                remaining,
+            });
+        }
+        self.fields.push(Field {
+            record: self.records.saturating_sub(1),
+            offset: self.pos,
+            len,
+            name,
+        });
+        let res = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(res)
+    }
+    /// Takes a length prefixed text field. Text over `limit` is skipped
+    /// without being copied and invalid utf-8 is kept lossily; both are
+    /// noted as warnings on the current record.
+    pub fn text(
+        &mut self,
+        name: &'static str,
+        len: u64,
+        limit: usize,
+    ) -> Result<Option<Text>, DecodeError> {
+        let offset = self.pos;
+        if len > limit as u64 {
+            let too_large = DecodeError::FieldTooLarge {
+                field: name,
+                offset,
+                len,
+                limit,
+            };
+            if len > (self.buf.len() - self.pos) as u64 {
+                return Err(too_large);
+            }
+            self.take(name, len as usize)?;
+            self.warn(too_large);
+            return Ok(None);
+        }
+        let text = Text::from_bytes(self.take(name, len as usize)?.to_vec());
+        if !text.is_valid() {
+            self.warn(DecodeError::InvalidUtf8 {
+                field: name,
+                offset,
+            });
+        }
+        Ok(Some(text))
+    }
+    pub fn u8(&mut self, name: &'static str) -> Result<u8, DecodeError> {
+        Ok(self.take(name, 1)?[0])
+    }
+    pub fn u64(&mut self, name: &'static str) -> Result<u64, DecodeError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(name, 8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+    pub fn i64(&mut self, name: &'static str) -> Result<i64, DecodeError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(name, 8)?);
+        Ok(i64::from_be_bytes(buf))
+    }
+    pub fn array32(&mut self, name: &'static str) -> Result<[u8; 32], DecodeError> {
+        let mut buf = [0; 32];
+        buf.copy_from_slice(self.take(name, 32)?);
+        Ok(buf)
+    }
+
+    /// Hex dump of the whole response, one line group per decoded field,
+    /// followed by whatever could not be decoded.
+    pub fn dump(&self) -> String {
+        let mut out = format!("{} bytes, {} records", self.buf.len(), self.records);
+        let mut record = None;
+        for field in &self.fields {
+            if record != Some(field.record) {
+                record = Some(field.record);
+                write!(out, "\nrecord {} @ {:#06x}", field.record, field.offset).unwrap();
+            }
+            dump_bytes(
+                &mut out,
+                field.name,
+                field.offset,
+                &self.buf[field.offset..field.offset + field.len],
+            );
+        }
+        if !self.is_empty() {
+            write!(out, "\nundecoded @ {:#06x}", self.pos).unwrap();
+            dump_bytes(&mut out, "", self.pos, &self.buf[self.pos..]);
+        }
+        out
+    }
+}
+
+fn dump_bytes(out: &mut String, name: &str, offset: usize, bytes: &[u8]) {
+    if bytes.is_empty() {
+        write!(out, "\n  {:06x}  {:<10}", offset, name).unwrap();
+    }
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        write!(
+            out,
+            "\n  {:06x}  {:<10} {:<47}  |{}|",
+            offset + i * 16,
+            if i == 0 { name } else { "" },
+            hex.join(" "),
+            ascii
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+use super::*;
+use crate::{MAX_MESSAGE_SIZE, MAX_NAME_SIZE};
+
+#[test]
+fn truncated_fields_report_what_was_missing() {
+    let mut r = Reader::new(&[1, 0, 0, 0, 0, 0]);
+    assert_eq!(r.u8("inbound"), Ok(1));
+    assert_eq!(
+        r.u64("time"),
+        Err(DecodeError::UnexpectedEof {
+            field: "time",
+            offset: 1,
+            needed: 8,
+            remaining: 5,
+        })
+    );
+    // text shorter than its length prefix
+    let mut r = Reader::new(b"abc");
+    assert_eq!(
+        r.text("name", 4, MAX_NAME_SIZE),
+        Err(DecodeError::UnexpectedEof {
+            field: "name",
+            offset: 0,
+            needed: 4,
+            remaining: 3,
+        })
+    );
+}
+
+#[test]
+fn length_prefixes_over_the_limit_are_refused() {
+    let mut r = Reader::new(b"hello");
+    assert_eq!(
+        r.text("content", u64::MAX, MAX_MESSAGE_SIZE),
+        Err(DecodeError::FieldTooLarge {
+            field: "content",
+            offset: 0,
+            len: u64::MAX,
+            limit: MAX_MESSAGE_SIZE,
+        })
+    );
+    // nothing was taken
+    assert!(!r.is_empty());
+    // text over the limit that is all there is skipped, with a warning
+    let name = vec![b'x'; MAX_NAME_SIZE + 1];
+    let mut over = Reader::new(&name);
+    over.begin_record();
+    assert_eq!(
+        over.text("name", name.len() as u64, MAX_NAME_SIZE),
+        Ok(None)
+    );
+    assert!(over.is_empty());
+    assert_eq!(
+        over.into_warnings(),
+        [DecodeWarning {
+            record: 0,
+            error: DecodeError::FieldTooLarge {
+                field: "name",
+                offset: 0,
+                len: MAX_NAME_SIZE as u64 + 1,
+                limit: MAX_NAME_SIZE,
+            },
+        }]
+    );
+    assert_eq!(
+        r.text("content", 5, 5),
+        Ok(Some(Text::from_bytes(b"hello".to_vec())))
+    );
+}
+
+#[test]
+fn dumps_show_each_field_in_hex_under_its_record() {
+    let mut buf = vec![1];
+    buf.extend_from_slice(b"a longer field, in two rows");
+    buf.extend_from_slice(&[0xff, 0x00]);
+    let mut r = Reader::new(&buf);
+    r.begin_record();
+    r.u8("inbound").unwrap();
+    r.take("content", 27).unwrap();
+    r.begin_record();
+    r.take("empty", 0).unwrap();
+    assert_eq!(
+        r.dump(),
+        "30 bytes, 2 records\n\
+         record 0 @ 0x0000\n  \
+         000000  inbound    01                                               |.|\n  \
+         000001  content    61 20 6c 6f 6e 67 65 72 20 66 69 65 6c 64 2c 20  |a longer field, |\n  \
+         000011             69 6e 20 74 77 6f 20 72 6f 77 73                 |in two rows|\n\
+         record 1 @ 0x001c\n  \
+         00001c  empty     \n\
+         undecoded @ 0x001c\n  \
+         00001c             ff 00                                            |..|"
+    );
+}